@@ -3,22 +3,47 @@ mod types;
 mod utils;
 
 use candid::{Nat, Principal};
+use ethers_core::types::Address;
+use ic_cdk::api::management_canister::http_request::{HttpResponse, TransformArgs};
 use ic_cdk::update;
 use ic_cdk_macros::query;
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
 use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap};
 use services::events::trigger_events;
+use services::voting::{
+    get_voting_power, invalidate_voting_power_cache, snapshot_blocks, BlockHeight,
+};
+use services::{
+    comments, events, execution, lifecycle, roles, settings, subscriptions, templates, vote_history,
+};
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::time::Duration;
-use types::event::{EventData, EventTrigger};
+use types::comment::{Comment, CommentData, CommentKey, CommentModerationData, CommentPage};
+use types::delegation::{DelegatedPower, Delegation, DelegationData, DelegationKey};
+use types::event::{Event, EventData, EventTrigger};
 use types::evm_strategy::{self, EvmStrategy};
-use types::proposal::Proposal;
-use types::proposal_option_vote::ProposalOptionVote;
-use types::proposal_options::{InsertProposalOption, ProposalOption};
-use types::space::{self, Space};
-use types::strategy::{Strategy, StrategyData};
+use types::proposal::{
+    Proposal, ProposalAction, ProposalActionData, ProposalData, ProposalFilter, ProposalPage,
+    ProposalStatus, ProposalSummary, SnapshotBlock, MAX_DESCRIPTION_LENGTH,
+};
+use types::proposal_metadata::ProposalMetadata;
+use types::proposal_option_vote::{
+    AddressVoteCursor, AddressVoteKey, AddressVotePage, ProposalOptionVote, VoteCursor, VoteFilter,
+    VotePage,
+};
+use types::proposal_options::{InsertProposalOption, ProposalOption, ProposalOptionSummary};
+use types::proposal_template::{InsertProposalTemplate, ProposalTemplate};
+use types::role::{Authorization, MemberKey, RoleData, SpaceMember, SpaceRole};
+use types::space::{
+    self, AcceptOwnershipData, InsertSpace, Ownership, Space, SpaceFilter, SpacePage, SpaceSort,
+    SpaceSummary,
+};
+use types::strategy::Strategy;
+use types::subscription::{Subscription, SubscriptionData, SubscriptionKey, UnfollowData};
 use types::vote::VoteData;
+use types::voting_power_cache::{CachedVotingPower, VotingPowerCacheKey};
+use types::webhook_delivery::{WebhookDelivery, WebhookDeliveryKey};
+use utils::{normalize_address, verify_signature};
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 
@@ -31,102 +56,218 @@ thread_local! {
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(0))),
         )
     );
+    static VOTING_POWER_CACHE: RefCell<StableBTreeMap<VotingPowerCacheKey, CachedVotingPower, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1))),
+        )
+    );
+    static DELEGATIONS: RefCell<StableBTreeMap<DelegationKey, Delegation, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2))),
+        )
+    );
+    static COMMENTS: RefCell<StableBTreeMap<CommentKey, Comment, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3))),
+        )
+    );
+    static MEMBERS: RefCell<StableBTreeMap<MemberKey, SpaceMember, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4))),
+        )
+    );
+    static VOTES_BY_ADDRESS: RefCell<StableBTreeMap<AddressVoteKey, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(5))),
+        )
+    );
+    static SUBSCRIPTIONS: RefCell<StableBTreeMap<SubscriptionKey, Subscription, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(6))),
+        )
+    );
+    static WEBHOOK_DELIVERIES: RefCell<StableBTreeMap<WebhookDeliveryKey, WebhookDelivery, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(7))),
+        )
+    );
     pub static ECDSA_KEY: RefCell<String> = RefCell::new(String::default());
 }
 
+fn caller_is_controller() -> Result<(), String> {
+    if ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        Ok(())
+    } else {
+        Err("Caller is not a controller".to_owned())
+    }
+}
+
+/// Rewrites spaces saved by earlier versions in the current layout and
+/// schedules again the timers the upgrade dropped. Proposals that ended
+/// without being finalized are left to `finalize_proposal`. Votes of migrated
+/// spaces are indexed by `rebuild_vote_index`.
+#[ic_cdk::post_upgrade]
+fn post_upgrade() {
+    let spaces: Vec<Space> = SPACES.with(|s| s.borrow().iter().map(|(_, v)| v).collect());
+    let now = lifecycle::now();
+
+    for space in spaces {
+        for proposal in space.proposals.iter() {
+            match proposal.status {
+                ProposalStatus::Pending | ProposalStatus::Active if proposal.vote_end >= now => {
+                    lifecycle::schedule_timers(proposal)
+                }
+                ProposalStatus::Succeeded if proposal.eta.is_some() => {
+                    execution::schedule(proposal)
+                }
+                _ => {}
+            }
+        }
+
+        SPACES.with(|s| s.borrow_mut().insert(space.id, space));
+    }
+}
+
 //SPACES
 
 #[query]
 fn get_spaces() -> Option<Vec<Space>> {
-    Some(SPACES.with(|p| p.borrow().iter().map(|(_, v)| v.clone()).collect()))
+    Some(SPACES.with(|p| p.borrow().iter().map(|(_, v)| v.redacted()).collect()))
+}
+
+#[query(name = "get_space")]
+fn query_space(id: u32) -> Option<Space> {
+    get_space(id).map(Space::redacted)
 }
 
-#[query]
 fn get_space(id: u32) -> Option<Space> {
     SPACES.with(|p| p.borrow().get(&id))
 }
 
+pub const MAX_PAGE_SIZE: u32 = 100;
+
+/// Returns up to `limit` space summaries matching `filter`, in the requested
+/// order. `cursor` is the position to continue from, as returned in
+/// `next_cursor` by the previous page.
+#[query]
+fn list_spaces(cursor: Option<u32>, limit: u32, filter: SpaceFilter) -> SpacePage {
+    let name = filter.name.map(|n| n.trim().to_lowercase());
+    let mut summaries: Vec<SpaceSummary> = SPACES.with(|p| {
+        p.borrow()
+            .iter()
+            .filter(|(_, space)| match name {
+                Some(ref name) => space.name.to_lowercase().contains(name),
+                None => true,
+            })
+            .map(|(_, space)| space_summary(&space))
+            .collect()
+    });
+
+    summaries.sort_by(|a, b| {
+        let order = match filter.sort {
+            SpaceSort::Id => a.id.cmp(&b.id),
+            SpaceSort::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            SpaceSort::ProposalCount => a.proposal_count.cmp(&b.proposal_count),
+            SpaceSort::MemberCount => a.member_count.cmp(&b.member_count),
+            SpaceSort::LastActivity => a.last_activity.cmp(&b.last_activity),
+        };
+        // Ties keep a stable order across pages.
+        let order = order.then(a.id.cmp(&b.id));
+
+        if filter.descending {
+            order.reverse()
+        } else {
+            order
+        }
+    });
+
+    let start = cursor.unwrap_or(0) as usize;
+    let limit = limit.clamp(1, MAX_PAGE_SIZE) as usize;
+    let end = (start + limit).min(summaries.len());
+
+    SpacePage {
+        spaces: summaries.get(start..end).unwrap_or_default().to_vec(),
+        next_cursor: if end < summaries.len() {
+            Some(end as u32)
+        } else {
+            None
+        },
+    }
+}
+
+fn space_summary(space: &Space) -> SpaceSummary {
+    let last_activity = space
+        .proposals
+        .iter()
+        .flat_map(|p| {
+            std::iter::once(p.date_created).chain(
+                p.options
+                    .iter()
+                    .flat_map(|o| o.votes.iter().map(|v| v.timestamp)),
+            )
+        })
+        .max()
+        .unwrap_or(0);
+
+    SpaceSummary {
+        id: space.id,
+        name: space.name.clone(),
+        icon_link: space.icon_link.clone(),
+        website_link: space.website_link.clone(),
+        owner_address: space.owner_address.clone(),
+        proposal_count: space.proposals.len() as u32,
+        member_count: roles::member_count(space.id),
+        last_activity,
+    }
+}
+
 #[update]
-fn insert_space(
-    name: String,
-    icon_link: String,
-    website_link: String,
-    owner_address: String,
-    vote_delay: u32,
-    vote_duration: u32,
-    min_vote_role: u32,
-    min_vote_power: Nat,
-    quorum: Nat,
-) -> Space {
+fn insert_space(settings: InsertSpace) -> Space {
     SPACES.with(|spaces_ref| {
         let mut spaces = spaces_ref.borrow_mut();
         let id = spaces.len() as u32 + 1;
-        let space = Space {
-            id,
-            name,
-            icon_link,
-            website_link,
-            owner_address,
-            vote_delay,
-            vote_duration,
-            min_vote_role,
-            min_vote_power,
-            quorum,
-            proposals: Vec::new(),
-            strategies: Vec::new(),
-            events: Vec::new(),
-        };
+        let space = Space::new(id, settings, Vec::new(), Vec::new(), Vec::new(), Vec::new());
         spaces.insert(id, space.clone());
         space
     })
 }
 
+/// Requires the Admin role. The owner can't be changed here, that goes
+/// through `propose_owner` and `accept_ownership`.
 #[update]
-fn update_space(
-    id: u32,
-    name: String,
-    icon_link: String,
-    website_link: String,
-    owner_address: String,
-    vote_delay: u32,
-    vote_duration: u32,
-    min_vote_role: u32,
-    min_vote_power: Nat,
-    quorum: Nat,
-) -> Option<Space> {
-    let space = get_space(id);
-    if space.is_none() {
-        return None;
-    }
-    let unwrapped_space = space.unwrap();
-    // let space = space.unwrap();
-    let new_space = Space {
-        id,
-        name,
-        icon_link,
-        website_link,
-        owner_address,
-        vote_delay,
-        vote_duration,
-        min_vote_role,
-        min_vote_power,
-        quorum,
-        proposals: unwrapped_space.proposals,
-        strategies: unwrapped_space.strategies,
-        events: unwrapped_space.events,
-    };
-
-    delete_space(id);
+fn update_space(id: u32, settings: InsertSpace, auth: Authorization) -> Result<Space, String> {
+    let mut space = get_space(id).ok_or("Invalid space id")?;
+    roles::authorize_role(
+        &space,
+        "update_space",
+        &(&settings,),
+        &auth,
+        SpaceRole::Admin,
+    )?;
+
+    settings::update_space(&mut space, settings)?;
+    store_space(&space);
+    events::notify(id, EventTrigger::SpaceUpdated, HashMap::new());
+
+    Ok(space)
+}
 
+fn store_space(space: &Space) {
     SPACES.with(|spaces_ref| {
         let mut spaces = spaces_ref.borrow_mut();
-        spaces.insert(id, new_space.clone());
+        spaces.insert(space.id, space.clone());
     });
+}
+
+fn update_space_ownership(id: u32, owner_address: String, ownership: Ownership) -> Space {
+    let mut space = get_space(id).expect("Invalid space id");
+    space.owner_address = owner_address;
+    space.ownership = ownership;
+    store_space(&space);
 
-    Some(new_space)
+    space
 }
 
-#[update]
 fn update_space_proposals(id: u32, proposals: Vec<Proposal>) {
     let space = get_space(id);
     if space.is_none() {
@@ -143,21 +284,34 @@ fn update_space_proposals(id: u32, proposals: Vec<Proposal>) {
 }
 
 #[update]
-fn delete_space(id: u32) -> Option<Space> {
+fn delete_space(id: u32, auth: Authorization) -> Result<Space, String> {
+    let space = get_space(id).ok_or("Invalid space id")?;
+    roles::authorize_role(&space, "delete_space", &(), &auth, SpaceRole::Admin)?;
+
     SPACES.with(|spaces_ref| {
         let mut spaces = spaces_ref.borrow_mut();
         spaces.remove(&id)
-    })
+    });
+
+    for proposal in &space.proposals {
+        comments::remove_proposal_comments(id, proposal.id);
+        vote_history::remove_proposal_votes(proposal);
+    }
+    roles::remove_space_members(id);
+    subscriptions::remove_space_subscriptions(id);
+    events::remove_space_deliveries(id);
+
+    Ok(space)
 }
 
-fn update_space_events(space_id: u32, events: Vec<types::event::Event>) {
+fn update_space_templates(space_id: u32, templates: Vec<ProposalTemplate>) {
     let space = get_space(space_id);
     if space.is_none() {
         return;
     }
     let space = space.unwrap();
     let mut new_space = space.clone();
-    new_space.events = events;
+    new_space.templates = templates;
 
     SPACES.with(|spaces_ref| {
         let mut spaces = spaces_ref.borrow_mut();
@@ -167,22 +321,48 @@ fn update_space_events(space_id: u32, events: Vec<types::event::Event>) {
 
 //PROPOSALS
 #[update]
-async fn insert_proposal(
-    space_id: u32,
-    title: String,
-    description: String,
-    mechanism: u32,
-    options: Vec<InsertProposalOption>,
-) -> Option<Proposal> {
-    let space = get_space(space_id);
-    if space.is_none() {
-        return None;
-    }
-    let space = space.unwrap();
-    let mut proposals = space.proposals;
-    let id = proposals.len() as u32 + 1;
-    // Convert nanoseconds to seconds
-    let date_created = ic_cdk::api::time() / 1_000_000_000;
+async fn insert_proposal(data: ProposalData) -> Result<Proposal, String> {
+    let message = data.message;
+    let author = verify_signature(&message, &data.signature, &message.address)?;
+
+    let space = get_space(message.space_id).ok_or("Invalid space id")?;
+    // Votes and the proposer check read voting power at the same blocks.
+    let snapshot = snapshot_blocks(space.id).await?;
+    check_proposer(&space, &author, &snapshot).await?;
+
+    // Re-read the space, proposals may have been added during the outcalls.
+    let space = get_space(message.space_id).ok_or("Invalid space id")?;
+
+    check_description(&message.description)?;
+    let metadata = message.metadata.unwrap_or_default().normalized()?;
+
+    let template = match message.template_id {
+        Some(template_id) => {
+            let template = templates::find_template(&space, template_id)?;
+            templates::check_proposal(template, message.mechanism, &message.description)?;
+            Some(template)
+        }
+        None => None,
+    };
+
+    let space_id = space.id;
+    let mut proposals = space.proposals.clone();
+    // Deleted proposals leave gaps, so the length is not a safe source of ids.
+    let id = proposals.iter().map(|p| p.id).max().unwrap_or(0) + 1;
+    let date_created = lifecycle::now();
+    let (vote_start, vote_end) = lifecycle::voting_window(
+        &space,
+        template,
+        date_created,
+        message.vote_start,
+        message.vote_end,
+    )?;
+
+    // Proposals submitted without options take the ones of their template.
+    let options = match template {
+        Some(template) if message.options.is_empty() => &template.options,
+        _ => &message.options,
+    };
 
     let mut new_options: Vec<ProposalOption> = Vec::new();
     let mut option_id = 1;
@@ -191,56 +371,265 @@ async fn insert_proposal(
         new_options.push(ProposalOption {
             id: option_id,
             name: option.name.clone(),
-            on_win_contract_address: "".to_string(),
-            on_win_bytecode: "".to_string(),
-            on_win_chain_id: 0,
+            on_win_actions: option.on_win_actions.clone(),
+            settings_changes: option.settings_changes.clone(),
             proposal_id: id,
             votes: Vec::new(),
         });
         option_id += 1;
     }
 
+    let status = if message.draft {
+        ProposalStatus::Draft
+    } else {
+        ProposalStatus::Pending
+    };
+
     let new_proposal = types::proposal::Proposal {
         id,
-        title: title.clone(),
-        description: description.clone(),
+        title: message.title,
+        description: message.description,
         date_created,
-        mechanism,
+        mechanism: message.mechanism,
         space_id,
         options: new_options,
+        status,
+        vote_start,
+        vote_end,
+        author: normalize_address(&author),
+        signature: data.signature,
+        queued_at: None,
+        eta: None,
+        execution_results: Vec::new(),
+        content_hash: None,
+        template_id: message.template_id,
+        metadata,
+        snapshot_blocks: Some(snapshot),
     };
 
+    roles::consume_timestamp(space_id, &new_proposal.author, message.timestamp)?;
+
     proposals.push(new_proposal.clone());
     update_space_proposals(space_id, proposals);
 
-    trigger_events(
-        space_id,
+    if !message.draft {
+        start_proposal(&space, &new_proposal).await;
+    }
+
+    Ok(new_proposal)
+}
+
+fn check_description(description: &str) -> Result<(), String> {
+    if description.len() > MAX_DESCRIPTION_LENGTH {
+        return Err(format!(
+            "Proposal description is longer than {} bytes",
+            MAX_DESCRIPTION_LENGTH
+        ));
+    }
+
+    Ok(())
+}
+
+/// Only allowlisted addresses may propose when the space has an allowlist,
+/// proposers need `min_proposal_role` when set and at least
+/// `min_proposal_power` at the proposal snapshot, delegations included.
+async fn check_proposer(
+    space: &Space,
+    author: &Address,
+    snapshot: &[SnapshotBlock],
+) -> Result<(), String> {
+    if let Some(ref role) = space.min_proposal_role {
+        roles::require_role(space, &normalize_address(author), role)?;
+    }
+
+    if !space.proposer_allowlist.is_empty() {
+        let author = normalize_address(author);
+        if !space
+            .proposer_allowlist
+            .iter()
+            .any(|a| a.eq_ignore_ascii_case(&author))
+        {
+            return Err("Address is not allowed to create proposals".to_owned());
+        }
+    }
+
+    if space.min_proposal_power > Nat::from(0 as u32) {
+        let block_height = BlockHeight::Snapshot(snapshot.to_vec());
+        let voting_power = get_voting_power(author, space.id, &block_height).await?;
+        if voting_power < space.min_proposal_power {
+            return Err("Insufficient voting power to create proposals".to_owned());
+        }
+    }
+
+    Ok(())
+}
+
+async fn start_proposal(space: &Space, proposal: &Proposal) {
+    let _ = trigger_events(
+        space.id,
         EventTrigger::ProposalCreated,
-        HashMap::from([
-            ("title", title.to_string()),
-            ("description", description.to_string()),
-        ]),
+        events::proposal_variables(proposal),
     )
     .await;
 
-    ic_cdk_timers::set_timer(
-        Duration::from_secs((space.vote_delay + space.vote_duration) as u64),
-        move || {
-            ic_cdk::spawn(async move {
-                trigger_events(
-                    space_id,
-                    EventTrigger::ProposalEnded,
-                    HashMap::from([
-                        ("title", title.to_string()),
-                        ("description", description.to_string()),
-                    ]),
-                )
-                .await;
-            });
-        },
+    lifecycle::schedule_timers(proposal);
+}
+
+/// Proposals are managed by their author or by a Moderator of the space.
+fn check_proposal_manager(space: &Space, proposal: &Proposal, signer: &str) -> Result<(), String> {
+    if proposal.author.eq_ignore_ascii_case(signer) {
+        return Ok(());
+    }
+
+    roles::require_role(space, signer, &SpaceRole::Moderator)
+}
+
+/// Moves a draft proposal to Pending. A voting window that would already have
+/// started is moved to start after the space's vote delay, keeping its length.
+#[update]
+async fn publish_proposal(
+    space_id: u32,
+    proposal_id: u32,
+    auth: Authorization,
+) -> Result<Proposal, String> {
+    let space = get_space(space_id).ok_or("Invalid space id")?;
+    let mut proposals = space.proposals.clone();
+    let proposal = proposals
+        .iter_mut()
+        .find(|p| p.id == proposal_id)
+        .ok_or("Invalid proposal id")?;
+
+    roles::authorize(
+        &space,
+        "publish_proposal",
+        &(proposal_id,),
+        &auth,
+        |signer| check_proposal_manager(&space, proposal, signer),
+    )?;
+
+    if !proposal.status.can_transition_to(ProposalStatus::Pending) {
+        return Err("Only draft proposals can be published".to_owned());
+    }
+
+    let template = proposal
+        .template_id
+        .and_then(|id| templates::find_template(&space, id).ok());
+
+    let now = lifecycle::now();
+    if proposal.vote_start < now {
+        let length = proposal.vote_end - proposal.vote_start;
+        proposal.vote_start = now + lifecycle::vote_delay(&space, template) as u64;
+        proposal.vote_end = proposal.vote_start + length;
+    }
+
+    proposal.status = ProposalStatus::Pending;
+    let proposal = proposal.clone();
+    update_space_proposals(space_id, proposals);
+
+    start_proposal(&space, &proposal).await;
+
+    Ok(proposal)
+}
+
+/// Settles the result of a proposal whose voting has ended. This normally
+/// runs from a timer, but anyone may call it, e.g. after an upgrade dropped
+/// the timers.
+#[update]
+async fn finalize_proposal(space_id: u32, proposal_id: u32) -> Result<Proposal, String> {
+    lifecycle::finalize(space_id, proposal_id).await
+}
+
+/// Runs the on-win actions and settings changes of a succeeded proposal once
+/// its execution delay has passed. A timer does this automatically; anyone
+/// may trigger it.
+#[update]
+async fn execute_proposal(space_id: u32, proposal_id: u32) -> Result<Proposal, String> {
+    execution::execute(space_id, proposal_id).await
+}
+
+/// Cancels a proposal that hasn't finished voting. Only its author or a
+/// Moderator may cancel it.
+#[update]
+fn cancel_proposal(data: ProposalActionData) -> Result<Proposal, String> {
+    let (space, proposal, signer) = verify_proposal_action(&data, ProposalAction::Cancel)?;
+
+    check_proposal_manager(&space, &proposal, &signer)?;
+
+    let proposal = match proposal.status {
+        ProposalStatus::Draft | ProposalStatus::Pending | ProposalStatus::Active => {
+            lifecycle::set_status(space.id, proposal.id, ProposalStatus::Cancelled)?
+        }
+        _ => return Err("Proposal can no longer be cancelled".to_owned()),
+    };
+
+    events::notify(
+        space.id,
+        EventTrigger::ProposalCancelled,
+        events::proposal_variables(&proposal),
     );
 
-    Some(new_proposal)
+    Ok(proposal)
+}
+
+/// Lets the space guardian block a proposal before its result is carried out.
+#[update]
+fn veto_proposal(data: ProposalActionData) -> Result<Proposal, String> {
+    let (space, proposal, signer) = verify_proposal_action(&data, ProposalAction::Veto)?;
+
+    match space.guardian_address {
+        Some(ref guardian) if guardian.eq_ignore_ascii_case(&signer) => {}
+        _ => return Err("Only the space guardian can veto a proposal".to_owned()),
+    }
+
+    let proposal = lifecycle::set_status(space.id, proposal.id, ProposalStatus::Vetoed)?;
+
+    events::notify(
+        space.id,
+        EventTrigger::ProposalCancelled,
+        events::proposal_variables(&proposal),
+    );
+
+    Ok(proposal)
+}
+
+fn verify_proposal_action(
+    data: &ProposalActionData,
+    action: ProposalAction,
+) -> Result<(Space, Proposal, String), String> {
+    let message = &data.message;
+    if message.action != action {
+        return Err("Invalid proposal action".to_owned());
+    }
+
+    let signer = verify_signature(message, &data.signature, &message.address)?;
+    let space = get_space(message.space_id).ok_or("Invalid space id")?;
+    let proposal = lifecycle::refresh_status(message.space_id, message.proposal_id)?;
+
+    Ok((space, proposal, normalize_address(&signer)))
+}
+
+#[query]
+fn get_proposals_by_status(space_id: u32, status: ProposalStatus) -> Option<Vec<Proposal>> {
+    let proposals = get_proposals(space_id)?;
+    Some(
+        proposals
+            .into_iter()
+            .filter(|p| p.status == status)
+            .collect(),
+    )
+}
+
+/// Proposals carrying `tag`, compared case-insensitively.
+#[query]
+fn get_proposals_by_tag(space_id: u32, tag: String) -> Option<Vec<Proposal>> {
+    let tag = tag.trim().to_lowercase();
+    let proposals = get_proposals(space_id)?;
+    Some(
+        proposals
+            .into_iter()
+            .filter(|p| p.metadata.tags.contains(&tag))
+            .collect(),
+    )
 }
 
 #[query]
@@ -249,7 +638,14 @@ fn get_proposals(space_id: u32) -> Option<Vec<Proposal>> {
     if space.is_none() {
         return None;
     }
-    Some(space.unwrap().proposals)
+    Some(
+        space
+            .unwrap()
+            .proposals
+            .into_iter()
+            .map(lifecycle::with_current_status)
+            .collect(),
+    )
 }
 
 #[query]
@@ -263,7 +659,7 @@ fn get_proposal(space_id: u32, proposal_id: u32) -> Option<Proposal> {
     if proposal.is_none() {
         return None;
     }
-    Some(proposal.unwrap().clone())
+    Some(lifecycle::with_current_status(proposal.unwrap().clone()))
 }
 
 #[update]
@@ -273,52 +669,69 @@ fn update_proposal(
     title: String,
     description: String,
     mechanism: u32,
-) -> Option<Proposal> {
-    let space = get_space(space_id);
-    if space.is_none() {
-        return None;
-    }
-    let mut proposals = space.unwrap().proposals;
-    let proposal = proposals.iter_mut().find(|p| p.id == proposal_id);
-    if proposal.is_none() {
-        return None;
+    metadata: Option<ProposalMetadata>,
+    auth: Authorization,
+) -> Result<Proposal, String> {
+    let space = get_space(space_id).ok_or("Invalid space id")?;
+    let proposal = lifecycle::refresh_status(space_id, proposal_id)?;
+    roles::authorize(
+        &space,
+        "update_proposal",
+        &(proposal_id, &title, &description, mechanism, &metadata),
+        &auth,
+        |signer| check_proposal_manager(&space, &proposal, signer),
+    )?;
+
+    lifecycle::ensure_editable(&proposal)?;
+    check_description(&description)?;
+    let metadata = metadata.map(ProposalMetadata::normalized).transpose()?;
+
+    if let Some(template_id) = proposal.template_id {
+        // A template deleted since the proposal was created no longer applies.
+        if let Ok(template) = templates::find_template(&space, template_id) {
+            templates::check_proposal(template, mechanism, &description)?;
+        }
     }
-    let proposal = proposal.unwrap();
-    let new_proposal = Proposal {
-        id: proposal_id,
-        title,
-        description,
-        date_created: proposal.date_created,
-        mechanism,
-        space_id,
-        options: proposal.options.clone(),
-    };
 
-    let index = proposals.iter().position(|p| p.id == proposal_id).unwrap();
-    proposals[index] = new_proposal.clone();
-    update_space_proposals(space_id, proposals);
-
-    Some(new_proposal)
+    lifecycle::modify_proposal(space_id, proposal_id, |proposal| {
+        proposal.title = title;
+        proposal.description = description;
+        proposal.mechanism = mechanism;
+        if let Some(metadata) = metadata {
+            proposal.metadata = metadata;
+        }
+        Ok(())
+    })
 }
 
 #[update]
-fn delete_proposal(space_id: u32, proposal_id: u32) -> Option<Proposal> {
-    let space = get_space(space_id);
-    if space.is_none() {
-        return None;
-    }
-    let proposals = space.unwrap().proposals;
-    let proposal = proposals.iter().find(|p| p.id == proposal_id);
-    if proposal.is_none() {
-        return None;
-    }
-    let proposal = proposal.unwrap();
+fn delete_proposal(
+    space_id: u32,
+    proposal_id: u32,
+    auth: Authorization,
+) -> Result<Proposal, String> {
+    let space = get_space(space_id).ok_or("Invalid space id")?;
+    roles::authorize_role(
+        &space,
+        "delete_proposal",
+        &(proposal_id,),
+        &auth,
+        SpaceRole::Moderator,
+    )?;
+
+    let proposals = space.proposals;
+    let proposal = proposals
+        .iter()
+        .find(|p| p.id == proposal_id)
+        .ok_or("Invalid proposal id")?;
     let index = proposals.iter().position(|p| p.id == proposal_id).unwrap();
     let mut new_proposals = proposals.clone();
     new_proposals.remove(index);
     update_space_proposals(space_id, new_proposals);
+    comments::remove_proposal_comments(space_id, proposal_id);
+    vote_history::remove_proposal_votes(proposal);
 
-    Some(proposal.clone())
+    Ok(proposal.clone())
 }
 
 //Options must not be editable from outside
@@ -345,6 +758,18 @@ fn update_proposal_options(
         mechanism: proposal.mechanism,
         space_id,
         options,
+        status: proposal.status,
+        vote_start: proposal.vote_start,
+        vote_end: proposal.vote_end,
+        author: proposal.author.clone(),
+        signature: proposal.signature.clone(),
+        queued_at: proposal.queued_at,
+        eta: proposal.eta,
+        execution_results: proposal.execution_results.clone(),
+        content_hash: proposal.content_hash.clone(),
+        template_id: proposal.template_id,
+        metadata: proposal.metadata.clone(),
+        snapshot_blocks: proposal.snapshot_blocks.clone(),
     };
 
     let index = proposals.iter().position(|p| p.id == proposal_id).unwrap();
@@ -360,38 +785,36 @@ fn update_proposal_options(
 fn insert_proposal_option(
     space_id: u32,
     proposal_id: u32,
-    name: String,
-    on_win_contract_address: String,
-    on_win_bytecode: String,
-    on_win_chain_id: u32,
-) -> Option<ProposalOption> {
-    let space = get_space(space_id);
-    if space.is_none() {
-        return None;
-    }
-    let mut proposals = space.unwrap().proposals;
-    let proposal = proposals.iter_mut().find(|p| p.id == proposal_id);
-    if proposal.is_none() {
-        return None;
-    }
-    let proposal = proposal.unwrap();
-    let options = proposal.options.clone();
-    let id = options.len() as u32 + 1;
+    option: InsertProposalOption,
+    auth: Authorization,
+) -> Result<ProposalOption, String> {
+    let space = get_space(space_id).ok_or("Invalid space id")?;
+    let proposal = lifecycle::refresh_status(space_id, proposal_id)?;
+    roles::authorize(
+        &space,
+        "insert_proposal_option",
+        &(proposal_id, &option),
+        &auth,
+        |signer| check_proposal_manager(&space, &proposal, signer),
+    )?;
+
+    lifecycle::ensure_editable(&proposal)?;
+
+    let mut options = proposal.options;
+    let id = options.iter().map(|o| o.id).max().unwrap_or(0) + 1;
     let new_option = ProposalOption {
         id,
-        name,
-        on_win_contract_address,
-        on_win_bytecode,
-        on_win_chain_id,
+        name: option.name,
+        on_win_actions: option.on_win_actions,
+        settings_changes: option.settings_changes,
         proposal_id,
         votes: Vec::new(),
     };
 
-    let mut options = proposal.options.clone();
     options.push(new_option.clone());
     update_proposal_options(space_id, proposal_id, options);
 
-    Some(new_option)
+    Ok(new_option)
 }
 
 #[query]
@@ -435,34 +858,36 @@ fn delete_proposal_option(
     space_id: u32,
     proposal_id: u32,
     option_id: u32,
-) -> Option<ProposalOption> {
-    let space = get_space(space_id);
-    if space.is_none() {
-        return None;
-    }
-    let mut proposals = space.unwrap().proposals;
-    let proposal = proposals.iter_mut().find(|p| p.id == proposal_id);
-    if proposal.is_none() {
-        return None;
-    }
-    let proposal = proposal.unwrap();
-    let options = proposal.options.clone();
-    let option = options.iter().find(|o| o.id == option_id);
-    if option.is_none() {
-        return None;
-    }
-    let option = option.unwrap();
-    let index = options.iter().position(|o| o.id == option_id).unwrap();
-
-    let mut new_options = options.clone();
-    new_options.remove(index);
+    auth: Authorization,
+) -> Result<ProposalOption, String> {
+    let space = get_space(space_id).ok_or("Invalid space id")?;
+    let proposal = lifecycle::refresh_status(space_id, proposal_id)?;
+    roles::authorize(
+        &space,
+        "delete_proposal_option",
+        &(proposal_id, option_id),
+        &auth,
+        |signer| check_proposal_manager(&space, &proposal, signer),
+    )?;
+
+    lifecycle::ensure_editable(&proposal)?;
+
+    let mut options = proposal.options;
+    let index = options
+        .iter()
+        .position(|o| o.id == option_id)
+        .ok_or("Invalid option id")?;
+    let option = options.remove(index);
 
-    update_proposal_options(space_id, proposal_id, new_options);
+    update_proposal_options(space_id, proposal_id, options);
+    for vote in &option.votes {
+        vote_history::unindex_vote(space_id, proposal_id, vote);
+    }
 
-    Some(option.clone())
+    Ok(option)
 }
 
-#[update]
+// Votes are only created through the signature checked `vote` endpoint.
 fn insert_vote(
     space_id: u32,
     proposal_id: u32,
@@ -472,6 +897,9 @@ fn insert_vote(
     timestamp: u64,
     signature: String,
     voting_power: Nat,
+    delegated_power: Vec<DelegatedPower>,
+    reason: Option<String>,
+    message_timestamp: Option<u64>,
 ) -> Option<ProposalOption> {
     let space = get_space(space_id);
     if space.is_none() {
@@ -490,7 +918,9 @@ fn insert_vote(
     }
     let option = option.unwrap();
     let mut votes = option.votes.clone();
-    let id = votes.len() as u32 + 1;
+    // Votes can be removed when a voter changes their choice, so the length
+    // of the list is not a safe source of new ids.
+    let id = votes.iter().map(|v| v.id).max().unwrap_or(0) + 1;
     let new_vote = ProposalOptionVote {
         id,
         user_address,
@@ -499,6 +929,9 @@ fn insert_vote(
         signature,
         voting_power,
         option_id,
+        delegated_power,
+        reason,
+        message_timestamp,
     };
 
     votes.push(new_vote.clone());
@@ -510,6 +943,7 @@ fn insert_vote(
     cloned_options[index].votes = votes;
     let new_option = cloned_options[index].clone();
     update_proposal_options(space_id, proposal_id, cloned_options);
+    vote_history::index_vote(space_id, proposal_id, &new_vote);
 
     Some(new_option)
 }
@@ -567,63 +1001,7 @@ fn get_vote(
     Some(vote.unwrap().clone())
 }
 
-#[update]
-fn update_vote(
-    space_id: u32,
-    proposal_id: u32,
-    option_id: u32,
-    vote_id: u32,
-    user_address: String,
-    vote_type: u32,
-    timestamp: u64,
-    signature: String,
-    voting_power: Nat,
-) -> Option<ProposalOptionVote> {
-    let space = get_space(space_id);
-    if space.is_none() {
-        return None;
-    }
-    let mut proposals = space.unwrap().proposals;
-    let proposal = proposals.iter_mut().find(|p| p.id == proposal_id);
-    if proposal.is_none() {
-        return None;
-    }
-    let proposal = proposal.unwrap();
-    let mut options = proposal.options.clone();
-    let option = options.iter_mut().find(|o| o.id == option_id);
-    if option.is_none() {
-        return None;
-    }
-    let option = option.unwrap();
-    let mut votes = option.votes.clone();
-    let vote = votes.iter_mut().find(|v| v.id == vote_id);
-    if vote.is_none() {
-        return None;
-    }
-    let new_vote = ProposalOptionVote {
-        id: vote_id,
-        user_address,
-        vote_type,
-        timestamp,
-        signature,
-        voting_power,
-        option_id,
-    };
-
-    let index = votes.iter().position(|v| v.id == vote_id).unwrap();
-    votes[index] = new_vote.clone();
-    let mut cloned_options = options.clone();
-    let index = cloned_options
-        .iter()
-        .position(|o| o.id == option_id)
-        .unwrap();
-    cloned_options[index].votes = votes;
-    update_proposal_options(space_id, proposal_id, cloned_options);
-
-    Some(new_vote)
-}
-
-#[update]
+#[update(guard = "caller_is_controller")]
 fn delete_vote(
     space_id: u32,
     proposal_id: u32,
@@ -663,6 +1041,7 @@ fn delete_vote(
         .unwrap();
     cloned_options[index].votes = new_votes;
     update_proposal_options(space_id, proposal_id, cloned_options);
+    vote_history::unindex_vote(space_id, proposal_id, vote);
 
     Some(vote.clone())
 }
@@ -698,41 +1077,26 @@ fn insert_evm_strategy(
     name: String,
     description: String,
     evm_strategy: EvmStrategy,
-) -> Option<Strategy> {
-    let space = get_space(space_id);
-    if space.is_none() {
-        return None;
-    }
-    let mut strategies = space.unwrap().strategies;
-    let id = strategies.len() as u32 + 1;
-    let new_strategy = types::strategy::Strategy {
-        id: id,
-        name,
-        description,
+    auth: Authorization,
+) -> Result<Strategy, String> {
+    let mut space = get_space(space_id).ok_or("Invalid space id")?;
+    roles::authorize_role(
+        &space,
+        "insert_evm_strategy",
+        &(&name, &description, &evm_strategy),
+        &auth,
+        SpaceRole::Admin,
+    )?;
+
+    let new_strategy = settings::insert_evm_strategy(&mut space, name, description, evm_strategy);
+    store_space(&space);
+    events::notify(
         space_id,
-        data: StrategyData::Evm(evm_strategy),
-    };
-
-    strategies.push(new_strategy.clone());
-    update_strategies(space_id, strategies);
-
-    Some(new_strategy)
-}
-
-#[update]
-fn update_strategies(id: u32, strategies: Vec<Strategy>) {
-    let space = get_space(id);
-    if space.is_none() {
-        return;
-    }
-    let space = space.unwrap();
-    let mut new_space = space.clone();
-    new_space.strategies = strategies;
+        EventTrigger::StrategyChanged,
+        events::strategy_variables(&new_strategy, "inserted"),
+    );
 
-    SPACES.with(|spaces_ref| {
-        let mut spaces = spaces_ref.borrow_mut();
-        spaces.insert(id, new_space.clone());
-    });
+    Ok(new_strategy)
 }
 
 #[update]
@@ -742,49 +1106,55 @@ fn update_evm_strategy(
     name: String,
     description: String,
     evm_strategy: EvmStrategy,
-) -> Option<Strategy> {
-    let space = get_space(space_id);
-    if space.is_none() {
-        return None;
-    }
-    let mut strategies = space.unwrap().strategies;
-    let id = strategies.len() as u32 + 1;
-    let new_strategy = types::strategy::Strategy {
-        id: id,
-        name,
-        description,
+    auth: Authorization,
+) -> Result<Strategy, String> {
+    let mut space = get_space(space_id).ok_or("Invalid space id")?;
+    roles::authorize_role(
+        &space,
+        "update_evm_strategy",
+        &(strategy_id, &name, &description, &evm_strategy),
+        &auth,
+        SpaceRole::Admin,
+    )?;
+
+    let new_strategy =
+        settings::update_evm_strategy(&mut space, strategy_id, name, description, evm_strategy)?;
+    store_space(&space);
+    invalidate_voting_power_cache(space_id, strategy_id);
+    events::notify(
         space_id,
-        data: StrategyData::Evm(evm_strategy),
-    };
-
-    let index = strategies.iter().position(|s| s.id == strategy_id).unwrap();
-    strategies[index] = new_strategy.clone();
-
-    update_strategies(space_id, strategies);
+        EventTrigger::StrategyChanged,
+        events::strategy_variables(&new_strategy, "updated"),
+    );
 
-    Some(new_strategy)
+    Ok(new_strategy)
 }
 
 #[update]
-fn delete_strategy(space_id: u32, strategy_id: u32) -> Option<Strategy> {
-    let space = get_space(space_id);
-    if space.is_none() {
-        return None;
-    }
-    let strategies = space.unwrap().strategies.clone();
-    let strategy = strategies.iter().find(|s| s.id == strategy_id);
-
-    if strategy.is_none() {
-        return None;
-    }
-
-    let strategy = strategy.unwrap();
-    let index = strategies.iter().position(|p| p.id == strategy_id).unwrap();
-    let mut new_strategies = strategies.clone();
-    new_strategies.remove(index);
-    update_strategies(space_id, new_strategies);
+fn delete_strategy(
+    space_id: u32,
+    strategy_id: u32,
+    auth: Authorization,
+) -> Result<Strategy, String> {
+    let mut space = get_space(space_id).ok_or("Invalid space id")?;
+    roles::authorize_role(
+        &space,
+        "delete_strategy",
+        &(strategy_id,),
+        &auth,
+        SpaceRole::Admin,
+    )?;
+
+    let strategy = settings::delete_strategy(&mut space, strategy_id)?;
+    store_space(&space);
+    invalidate_voting_power_cache(space_id, strategy_id);
+    events::notify(
+        space_id,
+        EventTrigger::StrategyChanged,
+        events::strategy_variables(&strategy, "deleted"),
+    );
 
-    Some(strategy.clone())
+    Ok(strategy)
 }
 
 //EVENTS
@@ -794,8 +1164,8 @@ fn get_events_by_space(space_id: u32) -> Option<Vec<types::event::Event>> {
     if space.is_none() {
         return None;
     }
-    let events = space.unwrap().events.clone();
-    Some(events)
+    let events = space.unwrap().events.into_iter().map(Event::redacted);
+    Some(events.collect())
 }
 
 #[update]
@@ -803,22 +1173,21 @@ fn insert_event(
     space_id: u32,
     event_trigger: EventTrigger,
     data: EventData,
-) -> Option<types::event::Event> {
-    let space = get_space(space_id);
-    if space.is_none() {
-        return None;
-    }
-    let mut events = space.unwrap().events;
-    let new_event = types::event::Event {
-        event_trigger,
-        space_id,
-        data,
-    };
-
-    events.push(new_event.clone());
-    update_space_events(space_id, events);
-
-    Some(new_event)
+    auth: Authorization,
+) -> Result<types::event::Event, String> {
+    let mut space = get_space(space_id).ok_or("Invalid space id")?;
+    roles::authorize_role(
+        &space,
+        "insert_event",
+        &(&event_trigger, &data),
+        &auth,
+        SpaceRole::Admin,
+    )?;
+
+    let new_event = settings::insert_event(&mut space, event_trigger, data)?;
+    store_space(&space);
+
+    Ok(new_event.redacted())
 }
 
 ic_cdk::export_candid!();
@@ -0,0 +1,194 @@
+use ic_cdk::{query, update};
+
+use super::roles;
+use crate::{
+    get_space,
+    types::{
+        comment::{Comment, CommentData, CommentKey, CommentModerationData, CommentPage},
+        role::SpaceRole,
+    },
+    utils::{normalize_address, verify_signature},
+    COMMENTS, MAX_PAGE_SIZE,
+};
+
+const MAX_COMMENT_LENGTH: usize = 5000;
+
+/// Posts a signed comment on a proposal, optionally as a reply to another
+/// comment of the same proposal.
+#[update]
+fn add_comment(data: CommentData) -> Result<Comment, String> {
+    let message = data.message;
+    let author = verify_signature(&message, &data.signature, &message.author)?;
+
+    let space = get_space(message.space_id).ok_or("Invalid space id")?;
+    if !space.proposals.iter().any(|p| p.id == message.proposal_id) {
+        return Err("Invalid proposal id".to_owned());
+    }
+
+    if message.body.trim().is_empty() {
+        return Err("Comment body is empty".to_owned());
+    }
+
+    if message.body.len() > MAX_COMMENT_LENGTH {
+        return Err("Comment body is too long".to_owned());
+    }
+
+    let comments = proposal_comments(message.space_id, message.proposal_id);
+
+    if let Some(parent_id) = message.parent_id {
+        if !comments.iter().any(|c| c.id == parent_id) {
+            return Err("Invalid parent comment id".to_owned());
+        }
+    }
+
+    let author = normalize_address(&author);
+    roles::consume_timestamp(message.space_id, &author, message.timestamp)?;
+
+    let comment = Comment {
+        id: comments.last().map_or(0, |c| c.id + 1),
+        space_id: message.space_id,
+        proposal_id: message.proposal_id,
+        parent_id: message.parent_id,
+        author,
+        body: message.body,
+        timestamp: message.timestamp,
+        signature: data.signature,
+        hidden: false,
+    };
+
+    COMMENTS.with(|c| {
+        c.borrow_mut()
+            .insert(comment_key(&comment), comment.clone())
+    });
+
+    Ok(comment)
+}
+
+/// Hides a comment from listings, or shows it again. Only Moderators may
+/// moderate comments.
+#[update]
+fn moderate_comment(data: CommentModerationData) -> Result<Comment, String> {
+    let message = &data.message;
+    let signer = verify_signature(message, &data.signature, &message.address)?;
+    let signer = normalize_address(&signer);
+    let space = get_space(message.space_id).ok_or("Invalid space id")?;
+
+    roles::require_role(&space, &signer, &SpaceRole::Moderator)?;
+
+    let key = CommentKey {
+        space_id: message.space_id,
+        proposal_id: message.proposal_id,
+        comment_id: message.comment_id,
+    };
+
+    if !COMMENTS.with(|c| c.borrow().contains_key(&key)) {
+        return Err("Invalid comment id".to_owned());
+    }
+
+    // Old moderation messages can't be replayed to flip the comment back.
+    roles::consume_timestamp(space.id, &signer, message.timestamp)?;
+
+    COMMENTS.with(|c| {
+        let mut comments = c.borrow_mut();
+        let mut comment = comments.get(&key).ok_or("Invalid comment id")?;
+
+        comment.hidden = message.hidden;
+        comments.insert(key, comment.clone());
+
+        Ok(comment)
+    })
+}
+
+#[query]
+fn get_comment(space_id: u32, proposal_id: u32, comment_id: u32) -> Option<Comment> {
+    COMMENTS
+        .with(|c| {
+            c.borrow().get(&CommentKey {
+                space_id,
+                proposal_id,
+                comment_id,
+            })
+        })
+        .map(Comment::redacted)
+}
+
+/// Returns up to `limit` comments of a proposal in posting order, starting
+/// after the comment id given as `cursor`. Hidden comments are only included
+/// when asked for, e.g. for moderation views, and never with their body.
+#[query]
+fn get_comments(
+    space_id: u32,
+    proposal_id: u32,
+    cursor: Option<u32>,
+    limit: u32,
+    include_hidden: bool,
+) -> CommentPage {
+    let limit = limit.clamp(1, MAX_PAGE_SIZE) as usize;
+    let start = CommentKey {
+        space_id,
+        proposal_id,
+        comment_id: cursor.map_or(0, |id| id.saturating_add(1)),
+    };
+
+    let mut comments: Vec<Comment> = COMMENTS.with(|c| {
+        c.borrow()
+            .range(start..)
+            .take_while(|(key, _)| key.space_id == space_id && key.proposal_id == proposal_id)
+            .map(|(_, comment)| comment)
+            .filter(|comment| include_hidden || !comment.hidden)
+            .map(Comment::redacted)
+            .take(limit + 1)
+            .collect()
+    });
+
+    let next_cursor = if comments.len() > limit {
+        comments.truncate(limit);
+        comments.last().map(|c| c.id)
+    } else {
+        None
+    };
+
+    CommentPage {
+        comments,
+        next_cursor,
+    }
+}
+
+/// Drops the comments of a deleted proposal.
+pub fn remove_proposal_comments(space_id: u32, proposal_id: u32) {
+    let keys: Vec<CommentKey> = proposal_comments(space_id, proposal_id)
+        .iter()
+        .map(comment_key)
+        .collect();
+
+    COMMENTS.with(|c| {
+        let mut comments = c.borrow_mut();
+        for key in keys {
+            comments.remove(&key);
+        }
+    });
+}
+
+fn proposal_comments(space_id: u32, proposal_id: u32) -> Vec<Comment> {
+    let start = CommentKey {
+        space_id,
+        proposal_id,
+        comment_id: 0,
+    };
+
+    COMMENTS.with(|c| {
+        c.borrow()
+            .range(start..)
+            .take_while(|(key, _)| key.space_id == space_id && key.proposal_id == proposal_id)
+            .map(|(_, comment)| comment)
+            .collect()
+    })
+}
+
+fn comment_key(comment: &Comment) -> CommentKey {
+    CommentKey {
+        space_id: comment.space_id,
+        proposal_id: comment.proposal_id,
+        comment_id: comment.id,
+    }
+}
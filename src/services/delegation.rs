@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+
+use ethers_core::types::Address;
+use ic_cdk::{query, update};
+
+use crate::{
+    get_space,
+    types::delegation::{Delegation, DelegationData, DelegationKey},
+    utils::{normalize_address, verify_signature},
+    DELEGATIONS,
+};
+
+/// Sets or revokes the delegation of `message.delegator`, either for one space
+/// or globally. Each message must carry a newer timestamp than the last one
+/// accepted for the same delegator and scope.
+#[update]
+fn delegate(data: DelegationData) -> Result<Delegation, String> {
+    let message = data.message;
+    let delegator = verify_signature(&message, &data.signature, &message.delegator)?;
+
+    let delegate = match message.delegate {
+        Some(ref delegate) => {
+            let delegate = delegate
+                .parse::<Address>()
+                .map_err(|_| "Invalid delegate address")?;
+
+            if delegate == delegator {
+                return Err("Unable to delegate to self".to_owned());
+            }
+
+            Some(normalize_address(&delegate))
+        }
+        None => None,
+    };
+
+    if let Some(space_id) = message.space_id {
+        if get_space(space_id).is_none() {
+            return Err("Invalid space id".to_owned());
+        }
+    }
+
+    let key = DelegationKey {
+        delegator: normalize_address(&delegator),
+        space_id: message.space_id,
+    };
+
+    if let Some(previous) = DELEGATIONS.with(|d| d.borrow().get(&key)) {
+        if previous.timestamp >= message.timestamp {
+            return Err("Delegation message is outdated".to_owned());
+        }
+    }
+
+    let delegation = Delegation {
+        delegator: key.delegator.clone(),
+        delegate,
+        space_id: message.space_id,
+        timestamp: message.timestamp,
+        signature: data.signature,
+    };
+
+    DELEGATIONS.with(|d| d.borrow_mut().insert(key, delegation.clone()));
+
+    Ok(delegation)
+}
+
+/// Returns the delegation of `delegator` that applies to `space_id`.
+#[query]
+fn get_delegation(space_id: u32, delegator: String) -> Option<Delegation> {
+    let delegator = delegator.to_lowercase();
+
+    get_space_delegations(space_id)
+        .into_iter()
+        .find(|d| d.delegator == delegator)
+}
+
+#[query]
+fn get_delegators(space_id: u32, delegate: String) -> Vec<Delegation> {
+    let delegate = delegate.to_lowercase();
+
+    get_space_delegations(space_id)
+        .into_iter()
+        .filter(|d| d.delegate.as_ref() == Some(&delegate))
+        .collect()
+}
+
+#[query]
+fn get_delegates(space_id: u32) -> Vec<String> {
+    let mut delegates: Vec<String> = get_space_delegations(space_id)
+        .into_iter()
+        .filter_map(|d| d.delegate)
+        .collect();
+
+    delegates.sort();
+    delegates.dedup();
+    delegates
+}
+
+/// Active delegations in a space, one per delegator. An active space scoped
+/// delegation wins over a global one; revoking it falls back to the global one.
+pub fn get_space_delegations(space_id: u32) -> Vec<Delegation> {
+    let mut by_delegator: BTreeMap<String, Delegation> = BTreeMap::new();
+
+    DELEGATIONS.with(|d| {
+        for (key, delegation) in d.borrow().iter() {
+            if delegation.delegate.is_none() {
+                continue;
+            }
+
+            match key.space_id {
+                Some(id) if id == space_id => {
+                    by_delegator.insert(key.delegator, delegation);
+                }
+                None => {
+                    by_delegator.entry(key.delegator).or_insert(delegation);
+                }
+                _ => {}
+            }
+        }
+    });
+
+    by_delegator.into_values().collect()
+}
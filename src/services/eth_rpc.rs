@@ -10,7 +10,7 @@ use ethers_core::{
 use ic_stable_structures::Storable;
 use std::{cell::RefCell, str::FromStr};
 
-use candid::{Nat, Principal};
+use candid::Principal;
 use ethers_core::{
     abi::{Abi, Address, FunctionExt, Token},
     types::{Eip1559TransactionRequest, NameOrAddress, H160, U256},
@@ -69,6 +69,29 @@ struct JsonRpcError {
     message: String,
 }
 
+// Outcall fees on a 13 node subnet
+const HTTP_BASE_COST: u128 = 49_140_000;
+const HTTP_REQUEST_BYTE_COST: u128 = 5_200;
+const HTTP_RESPONSE_BYTE_COST: u128 = 10_400;
+
+// The EVM RPC canister runs on a larger subnet and adds its own fees on top
+// of the outcall. It refunds what it doesn't use.
+const RPC_COST_FACTOR: u128 = 4;
+const MIN_RPC_CYCLES: u128 = 2_000_000_000;
+
+/// Cycles for an outcall on a 13 node subnet: a base fee plus fees per
+/// request byte and per byte of the maximum response size.
+pub fn outcall_cost(request_bytes: usize, response_bytes: u64) -> u128 {
+    HTTP_BASE_COST
+        + HTTP_REQUEST_BYTE_COST * request_bytes as u128
+        + HTTP_RESPONSE_BYTE_COST * response_bytes as u128
+}
+
+/// Cycles attached to a JSON-RPC request through the EVM RPC canister.
+fn rpc_cycles(payload: &str, max_response_bytes: u64) -> u128 {
+    (RPC_COST_FACTOR * outcall_cost(payload.len(), max_response_bytes)).max(MIN_RPC_CYCLES)
+}
+
 fn ecdsa_key_id() -> EcdsaKeyId {
     EcdsaKeyId {
         curve: ic_cdk::api::management_canister::ecdsa::EcdsaCurve::Secp256k1,
@@ -77,7 +100,7 @@ fn ecdsa_key_id() -> EcdsaKeyId {
     }
 }
 
-async fn pubkey_and_signature(message_hash: Vec<u8>) -> (Vec<u8>, Vec<u8>) {
+async fn pubkey_and_signature(message_hash: Vec<u8>) -> Result<(Vec<u8>, Vec<u8>), String> {
     // Fetch the pubkey and the signature concurrently to reduce latency.
     let (pubkey, response) = futures::join!(
         ecdsa_public_key(EcdsaPublicKeyArgument {
@@ -91,18 +114,26 @@ async fn pubkey_and_signature(message_hash: Vec<u8>) -> (Vec<u8>, Vec<u8>) {
             key_id: ecdsa_key_id(),
         })
     );
-    (
-        pubkey.unwrap().0.public_key,
-        response.expect("failed to sign the message").0.signature,
-    )
+    let pubkey = pubkey.map_err(|err| format!("Unable to fetch the public key: {:?}", err))?;
+    let response = response.map_err(|err| format!("Unable to sign the message: {:?}", err))?;
+
+    Ok((pubkey.0.public_key, response.0.signature))
 }
 
-pub async fn sign_transaction(to: &Address, data: &Bytes, chain_id: u64) -> String {
+pub async fn sign_transaction(
+    to: &Address,
+    value: U256,
+    data: &Bytes,
+    chain_id: u64,
+    nonce: U256,
+) -> Result<String, String> {
     use ethers_core::types::Signature;
 
     let tx = Eip1559TransactionRequest {
         from: None,
         to: Some(NameOrAddress::Address(*to)),
+        nonce: Some(nonce),
+        value: Some(value),
         gas: Some(300_000.into()),
         max_priority_fee_per_gas: None,
         data: Some(data.clone()),
@@ -115,10 +146,10 @@ pub async fn sign_transaction(to: &Address, data: &Bytes, chain_id: u64) -> Stri
 
     let tx_hash = keccak256(&unsigned_tx_bytes);
 
-    let (pubkey, signature) = pubkey_and_signature(tx_hash.to_vec()).await;
+    let (pubkey, signature) = pubkey_and_signature(tx_hash.to_vec()).await?;
 
     let signature = Signature {
-        v: y_parity(&tx_hash.to_bytes(), &signature, &pubkey),
+        v: y_parity(&tx_hash.to_bytes(), &signature, &pubkey)?,
         r: U256::from_big_endian(&signature[0..32]),
         s: U256::from_big_endian(&signature[32..64]),
     };
@@ -126,22 +157,29 @@ pub async fn sign_transaction(to: &Address, data: &Bytes, chain_id: u64) -> Stri
     let mut signed_tx_bytes = tx.rlp_signed(&signature).to_vec();
     signed_tx_bytes.insert(0, 2);
 
-    format!("0x{}", hex::encode(signed_tx_bytes))
+    Ok(format!("0x{}", hex::encode(signed_tx_bytes)))
 }
 
-pub async fn call(data: String) -> Result<String, String> {
+pub async fn call(chain_id: u64, data: String) -> Result<String, String> {
+    let services = match chain_id {
+        1 => RpcServices::EthMainnet(None),
+        11155111 => RpcServices::EthSepolia(None),
+        _ => {
+            return Err(format!(
+                "Transactions on chain {} are not supported",
+                chain_id
+            ))
+        }
+    };
+
     let (res,): (MultiSendRawTransactionResult,) = call_with_payment(
         CANISTER_ID,
         "eth_sendRawTransaction",
-        (
-            RpcServices::EthSepolia(None),
-            None::<RpcConfig>,
-            data.clone(),
-        ),
+        (services, None::<RpcConfig>, data.clone()),
         2_000_000_000_000,
     )
     .await
-    .unwrap();
+    .map_err(|err| format!("{:?}", err))?;
 
     match res {
         MultiSendRawTransactionResult::Consistent(SendRawTransactionResult::Ok(
@@ -158,56 +196,106 @@ pub async fn call(data: String) -> Result<String, String> {
 }
 
 /// Computes the parity bit allowing to recover the public key from the signature.
-fn y_parity(prehash: &[u8], sig: &[u8], pubkey: &[u8]) -> u64 {
-    let orig_key = VerifyingKey::from_sec1_bytes(pubkey).expect("failed to parse the pubkey");
-    let signature = Signature::try_from(sig).unwrap();
+fn y_parity(prehash: &[u8], sig: &[u8], pubkey: &[u8]) -> Result<u64, String> {
+    let orig_key =
+        VerifyingKey::from_sec1_bytes(pubkey).map_err(|_| "failed to parse the pubkey")?;
+    let signature = Signature::try_from(sig).map_err(|_| "failed to parse the signature")?;
     for parity in [0u8, 1] {
-        let recid = RecoveryId::try_from(parity).unwrap();
+        let recid = RecoveryId::try_from(parity).map_err(|_| "invalid recovery id")?;
         let recovered_key = VerifyingKey::recover_from_prehash(prehash, &signature, recid)
-            .expect("failed to recover key");
+            .map_err(|_| "failed to recover key")?;
         if recovered_key == orig_key {
-            return parity as u64;
+            return Ok(parity as u64);
         }
     }
 
-    panic!(
+    Err(format!(
         "failed to recover the parity bit from a signature; sig: {}, pubkey: {}",
         hex::encode(sig),
         hex::encode(pubkey)
-    )
+    ))
+}
+
+/// Maps a strategy chain id to the EVM RPC canister service that serves it.
+pub fn rpc_service(chain_id: u64) -> RpcService {
+    match chain_id {
+        1 => RpcService::EthMainnet(EthMainnetService::BlockPi),
+        11155111 => RpcService::EthSepolia(EthSepoliaService::BlockPi),
+        other => RpcService::Chain(other),
+    }
 }
 
-async fn next_id() -> Nat {
+/// Nonce to use for the next transaction sent by the canister on `chain_id`.
+pub async fn transaction_count(chain_id: u64) -> Result<U256, String> {
     let res: CallResult<(MultiGetTransactionCountResult,)> = call_with_payment(
         CANISTER_ID,
         "eth_getTransactionCount",
         (
-            RpcService::EthSepolia(EthSepoliaService::BlockPi),
+            rpc_service(chain_id),
             None::<RpcConfig>,
             GetTransactionCountArgs {
                 address: get_self_eth_address().await,
-                block: BlockTag::Latest,
+                block: BlockTag::Pending,
             },
         ),
         2_000_000_000,
     )
     .await;
+
     match res {
-        Ok((MultiGetTransactionCountResult::Consistent(GetTransactionCountResult::Ok(id)),)) => {
-            id.into()
+        Ok((MultiGetTransactionCountResult::Consistent(GetTransactionCountResult::Ok(count)),)) => {
+            Ok(count.into())
         }
-        Ok((inconsistent,)) => ic_cdk::trap(&format!("Inconsistent: {inconsistent:?}")),
-        Err(err) => ic_cdk::trap(&format!("{:?}", err)),
+        Ok((other,)) => Err(format!("{:?}", other)),
+        Err(err) => Err(format!("{:?}", err)),
+    }
+}
+
+/// Number of the latest block on `chain_id`.
+pub async fn block_number(chain_id: u64) -> Result<u64, String> {
+    let json_rpc_payload = serde_json::json!({
+        "id": 1,
+        "jsonrpc": "2.0",
+        "method": "eth_blockNumber",
+        "params": [],
+    })
+    .to_string();
+
+    let max_response_bytes = 256;
+    let cycles = rpc_cycles(&json_rpc_payload, max_response_bytes);
+    let res: CallResult<(RequestResult,)> = call_with_payment128(
+        CANISTER_ID,
+        "request",
+        (rpc_service(chain_id), json_rpc_payload, max_response_bytes),
+        cycles,
+    )
+    .await;
+
+    match res {
+        Ok((RequestResult::Ok(ok),)) => {
+            let json: JsonRpcResult =
+                serde_json::from_str(&ok).map_err(|_| "JSON was not well-formatted")?;
+            let result = json.result.ok_or("Block number is missing")?;
+
+            u64::from_str_radix(result.trim_start_matches("0x"), 16)
+                .map_err(|_| "Invalid block number".to_owned())
+        }
+        Ok((other,)) => Err(format!("{:?}", other)),
+        Err(err) => Err(format!("{:?}", err)),
     }
 }
 
 pub async fn eth_call(
+    chain_id: u64,
     contract_address: String,
     data: String,
     block_height: Option<String>,
+    max_response_bytes: u64,
 ) -> Result<String, String> {
+    // The id only pairs a response with its request, and every call is a
+    // separate outcall, so a constant avoids an extra round trip per call.
     let json_rpc_payload = serde_json::to_string(&JsonRpcRequest {
-        id: next_id().await.0.try_into().unwrap(),
+        id: 1,
         jsonrpc: "2.0".to_string(),
         method: "eth_call".to_string(),
         params: (
@@ -218,33 +306,32 @@ pub async fn eth_call(
             block_height.unwrap_or("latest".to_string()),
         ),
     })
-    .expect("Error while encoding JSON-RPC request");
+    .map_err(|_| "Error while encoding JSON-RPC request")?;
 
+    // Batched calls make both the request and the response grow, so the
+    // payment follows their size.
+    let cycles = rpc_cycles(&json_rpc_payload, max_response_bytes);
     let res: CallResult<(RequestResult,)> = call_with_payment128(
         CANISTER_ID,
         "request",
-        (
-            RpcService::EthSepolia(EthSepoliaService::BlockPi),
-            json_rpc_payload,
-            2048_u64,
-        ),
-        2_000_000_000,
+        (rpc_service(chain_id), json_rpc_payload, max_response_bytes),
+        cycles,
     )
     .await;
 
     match res {
         Ok((RequestResult::Ok(ok),)) => {
             let json: JsonRpcResult =
-                serde_json::from_str(&ok).expect("JSON was not well-formatted");
+                serde_json::from_str(&ok).map_err(|_| "JSON was not well-formatted")?;
 
-            if let Some(result) = json.result {
-                Ok(result)
-            } else {
-                Err("".to_owned())
+            match (json.result, json.error) {
+                (Some(result), _) => Ok(result),
+                (None, Some(error)) => Err(error.message),
+                (None, None) => Err("Contract call returned no result".to_owned()),
             }
         }
-
-        err => panic!("Response error: {err:?}"),
+        Ok((other,)) => Err(format!("Response error: {:?}", other)),
+        Err(err) => Err(format!("Response error: {:?}", err)),
     }
 }
 
@@ -273,4 +360,4 @@ pub async fn get_self_eth_address() -> String {
     }
 
     SELF_ETH_ADDRESS.with(|maybe_address| maybe_address.borrow().clone().unwrap())
-}
\ No newline at end of file
+}
@@ -1,64 +1,369 @@
 use std::collections::HashMap;
 
 use anyhow::{anyhow, Result};
+use hmac::{Hmac, Mac};
 use ic_cdk::api::management_canister::http_request::{
-    http_request, CanisterHttpRequestArgument, HttpHeader, HttpMethod,
+    http_request, CanisterHttpRequestArgument, HttpHeader, HttpMethod, HttpResponse, TransformArgs,
+    TransformContext,
 };
+use ic_cdk::query;
+use sha2::Sha256;
 
 use crate::{
-    get_events_by_space,
-    types::{event::Event, webhook_event::WebhookEvent},
+    get_space,
+    types::{
+        proposal::Proposal,
+        strategy::Strategy,
+        webhook_delivery::{WebhookDelivery, WebhookDeliveryKey},
+        webhook_event::{webhook_host, WebhookEvent, SIGNATURE_HEADER, TIMESTAMP_HEADER},
+    },
+    WEBHOOK_DELIVERIES,
 };
 
 use crate::types::event::{EventData, EventTrigger};
 
+use super::eth_rpc::outcall_cost;
+use super::lifecycle::{now, total_voting_power, winning_option};
+use super::subscriptions::subscribers;
+
+// Webhook responses are only checked for their status, so a small cap is
+// enough and keeps the cycles cost down.
+const MAX_RESPONSE_BYTES: u64 = 4_096;
+const MAX_DELIVERIES: usize = 100;
+
+// Webhook payloads are templates where `${name}` is replaced by the value of
+// the variable `name`. Values are JSON escaped but not quoted, so templates
+// put them inside strings, e.g. `{"content": "New proposal: ${title}"}`.
+// Unknown variables are left as they are.
+//
+// Every trigger:
+//   space_id, space_name, trigger
+// Proposal triggers (all but StrategyChanged and SpaceUpdated):
+//   proposal_id, title, description, author, status, vote_start, vote_end,
+//   turnout (total voting power cast), winning_option_id and
+//   winning_option_name (empty while no single option leads)
+// Vote:
+//   address, power, option_id, option_name
+// ProposalExecuted:
+//   tx_hash, hashes of the on-win transactions separated by commas
+// StrategyChanged:
+//   strategy_id, strategy_name, change (inserted, updated or deleted)
+//
+// ProposalCancelled also fires when the guardian vetoes a proposal, with
+// `status` telling the two apart.
+//
+// Webhooks with a secret are sent with an `X-DecGov-Timestamp` header and an
+// `X-DecGov-Signature: sha256=<hex>` header, see `sign_payload`.
+
 pub async fn trigger_events(
     space_id: u32,
     event_trigger: EventTrigger,
-    event_data: HashMap<&str, String>,
+    mut event_data: HashMap<&'static str, String>,
 ) -> Result<()> {
-    let events = get_events_by_space(space_id);
+    let space = get_space(space_id);
 
-    if events.is_none() {
+    if space.is_none() {
         return Err(anyhow!("Unable to get space by space id"));
     }
 
-    for event in events.unwrap().into_iter() {
+    let space = space.unwrap();
+    event_data.insert("space_id", space_id.to_string());
+    event_data.insert("space_name", space.name.clone());
+    event_data.insert("trigger", format!("{:?}", event_trigger));
+
+    for event in space.events.into_iter() {
         if event.event_trigger != event_trigger {
             continue;
         }
 
         match event.data {
-            EventData::Webhook(data) => handle_webhook_event(data, &event_data).await,
+            EventData::Webhook(data) => {
+                handle_webhook_event(space_id, &event_trigger, None, data, &event_data).await
+            }
             EventData::Evm(_) => panic!("Evm events are not implemented yet"),
         }
     }
 
+    // Followers get their own copy, rendered from their own payload. They are
+    // notified in the background so that their number doesn't hold up the
+    // call that triggered the event.
+    let followers = subscribers(space_id, &event_trigger);
+    if !followers.is_empty() {
+        ic_cdk::spawn(async move {
+            for subscription in followers {
+                if let Some(webhook) = subscription.webhook {
+                    let subscriber = Some(subscription.address);
+                    handle_webhook_event(
+                        space_id,
+                        &event_trigger,
+                        subscriber,
+                        webhook,
+                        &event_data,
+                    )
+                    .await;
+                }
+            }
+        });
+    }
+
     return Ok(());
 }
 
-async fn handle_webhook_event(event: WebhookEvent, event_data: &HashMap<&str, String>) {
+/// Triggers the events from synchronous code, without waiting for the
+/// webhooks to be delivered.
+pub fn notify(
+    space_id: u32,
+    event_trigger: EventTrigger,
+    event_data: HashMap<&'static str, String>,
+) {
+    ic_cdk::spawn(async move {
+        let _ = trigger_events(space_id, event_trigger, event_data).await;
+    });
+}
+
+pub fn proposal_variables(proposal: &Proposal) -> HashMap<&'static str, String> {
+    let winner = winning_option(proposal);
+
+    HashMap::from([
+        ("proposal_id", proposal.id.to_string()),
+        ("title", proposal.title.clone()),
+        ("description", proposal.description.clone()),
+        ("author", proposal.author.clone()),
+        ("status", format!("{:?}", proposal.status)),
+        ("vote_start", proposal.vote_start.to_string()),
+        ("vote_end", proposal.vote_end.to_string()),
+        ("turnout", total_voting_power(proposal).0.to_string()),
+        (
+            "winning_option_id",
+            winner.map_or(String::new(), |o| o.id.to_string()),
+        ),
+        (
+            "winning_option_name",
+            winner.map_or(String::new(), |o| o.name.clone()),
+        ),
+    ])
+}
+
+pub fn strategy_variables(strategy: &Strategy, change: &str) -> HashMap<&'static str, String> {
+    HashMap::from([
+        ("strategy_id", strategy.id.to_string()),
+        ("strategy_name", strategy.name.clone()),
+        ("change", change.to_owned()),
+    ])
+}
+
+async fn handle_webhook_event(
+    space_id: u32,
+    event_trigger: &EventTrigger,
+    subscriber: Option<String>,
+    event: WebhookEvent,
+    event_data: &HashMap<&str, String>,
+) {
     let mut payload = event.payload;
 
     for (key, value) in event_data.iter() {
         let new_key = format!("${{{}}}", key);
-        payload = payload.replace(&new_key, &value);
+        payload = payload.replace(&new_key, &json_escape(value));
     }
 
+    let mut headers = vec![HttpHeader {
+        name: String::from("Content-Type"),
+        value: String::from("application/json"),
+    }];
+
+    if let Some(ref secret) = event.secret {
+        let timestamp = now().to_string();
+        headers.push(HttpHeader {
+            name: TIMESTAMP_HEADER.to_owned(),
+            value: timestamp.clone(),
+        });
+        headers.push(HttpHeader {
+            name: SIGNATURE_HEADER.to_owned(),
+            value: format!("sha256={}", sign_payload(secret, &timestamp, &payload)),
+        });
+    }
+
+    headers.extend(event.headers.into_iter().map(|header| HttpHeader {
+        name: header.name,
+        value: header.value,
+    }));
+
     let json_utf8: Vec<u8> = payload.into_bytes();
     let request_body: Option<Vec<u8>> = Some(json_utf8);
 
     let request = CanisterHttpRequestArgument {
-        url: event.webhook_url,
+        url: event.webhook_url.clone(),
         method: HttpMethod::POST,
-        max_response_bytes: None,
-        headers: vec![HttpHeader {
-            name: String::from("Content-Type"),
-            value: String::from("application/json"),
-        }],
+        max_response_bytes: Some(MAX_RESPONSE_BYTES),
+        headers,
         body: request_body,
-        transform: None,
+        transform: Some(TransformContext::from_name(
+            "transform_webhook_response".to_owned(),
+            Vec::new(),
+        )),
+    };
+
+    let cycles = request_cost(&request);
+    let webhook_host = webhook_host(&event.webhook_url);
+    let (status, error) = match http_request(request, cycles).await {
+        Ok((response,)) => (u32::try_from(&response.status.0).ok(), None),
+        Err((code, message)) => {
+            let message = message.replace(&event.webhook_url, &webhook_host);
+            (None, Some(format!("{:?}: {}", code, message)))
+        }
     };
 
-    http_request(request, 2_000_000_000).await.unwrap();
+    record_delivery(WebhookDelivery {
+        id: 0,
+        space_id,
+        event_trigger: event_trigger.clone(),
+        webhook_host,
+        subscriber,
+        timestamp: now(),
+        status,
+        error,
+    });
+}
+
+/// Keeps only the status of webhook responses. Headers and bodies differ
+/// between replicas, e.g. dates and request ids, and would fail consensus.
+#[query]
+fn transform_webhook_response(args: TransformArgs) -> HttpResponse {
+    HttpResponse {
+        status: args.response.status,
+        headers: Vec::new(),
+        body: Vec::new(),
+    }
+}
+
+/// Most recent deliveries of a space's webhooks, newest first.
+#[query]
+fn get_webhook_deliveries(space_id: u32) -> Vec<WebhookDelivery> {
+    let mut deliveries = space_deliveries(space_id);
+    deliveries.reverse();
+    deliveries
+}
+
+/// Cycles for a webhook delivery, see `outcall_cost`.
+fn request_cost(request: &CanisterHttpRequestArgument) -> u128 {
+    let headers: usize = request
+        .headers
+        .iter()
+        .map(|h| h.name.len() + h.value.len())
+        .sum();
+    let transform = request
+        .transform
+        .as_ref()
+        .map_or(0, |t| t.function.0.method.len() + t.context.len());
+    let request_bytes =
+        request.url.len() + headers + request.body.as_ref().map_or(0, |b| b.len()) + transform;
+    let response_bytes = request.max_response_bytes.unwrap_or(MAX_RESPONSE_BYTES);
+
+    outcall_cost(request_bytes, response_bytes)
+}
+
+/// Stores a delivery, keeping the last `MAX_DELIVERIES` of the space.
+fn record_delivery(mut delivery: WebhookDelivery) {
+    let deliveries = space_deliveries(delivery.space_id);
+    delivery.id = deliveries.last().map_or(0, |d| d.id + 1);
+
+    WEBHOOK_DELIVERIES.with(|d| {
+        let mut stored = d.borrow_mut();
+        let excess = (deliveries.len() + 1).saturating_sub(MAX_DELIVERIES);
+        for old in deliveries.iter().take(excess) {
+            stored.remove(&WebhookDeliveryKey {
+                space_id: old.space_id,
+                delivery_id: old.id,
+            });
+        }
+
+        let key = WebhookDeliveryKey {
+            space_id: delivery.space_id,
+            delivery_id: delivery.id,
+        };
+        stored.insert(key, delivery);
+    });
+}
+
+/// Drops the delivery history of a deleted space.
+pub fn remove_space_deliveries(space_id: u32) {
+    let deliveries = space_deliveries(space_id);
+
+    WEBHOOK_DELIVERIES.with(|d| {
+        let mut stored = d.borrow_mut();
+        for delivery in deliveries {
+            stored.remove(&WebhookDeliveryKey {
+                space_id,
+                delivery_id: delivery.id,
+            });
+        }
+    });
+}
+
+fn space_deliveries(space_id: u32) -> Vec<WebhookDelivery> {
+    let start = WebhookDeliveryKey {
+        space_id,
+        delivery_id: 0,
+    };
+
+    WEBHOOK_DELIVERIES.with(|d| {
+        d.borrow()
+            .range(start..)
+            .take_while(|(key, _)| key.space_id == space_id)
+            .map(|(_, delivery)| delivery)
+            .collect()
+    })
+}
+
+/// Hex encoded HMAC-SHA256 of `{timestamp}.{payload}`. Receivers recompute it
+/// with the shared secret and should reject old timestamps to stop replays.
+fn sign_payload(secret: &str, timestamp: &str, payload: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(payload.as_bytes());
+
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Escapes `value` for use inside a JSON string, so that variables can't
+/// break out of the payload's structure.
+fn json_escape(value: &str) -> String {
+    let quoted = serde_json::to_string(value).unwrap_or_default();
+    quoted
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or_default()
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_timestamp_and_payload() {
+        // HMAC-SHA256 of `1700000000.{"event":"Vote"}`
+        assert_eq!(
+            sign_payload(
+                "whsec_0123456789abcdef",
+                "1700000000",
+                r#"{"event":"Vote"}"#
+            ),
+            "6dfc24be7973e62e93cf1f21123c158a89404acd5a00026e2ca0e957aada883a"
+        );
+    }
+
+    #[test]
+    fn escapes_json_string_content() {
+        assert_eq!(json_escape("plain"), "plain");
+        assert_eq!(json_escape(r#"say "hi""#), r#"say \"hi\""#);
+        assert_eq!(json_escape("a\\b\nc\t"), r#"a\\b\nc\t"#);
+        assert_eq!(json_escape("\u{1}"), r#"\u0001"#);
+        assert_eq!(
+            json_escape(r#"", "admin": true, "x": ""#),
+            r#"\", \"admin\": true, \"x\": \""#
+        );
+    }
 }
@@ -0,0 +1,219 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use ethers_core::types::{Address, Bytes, U256};
+
+use crate::{
+    services::{
+        eth_rpc::{call, sign_transaction, transaction_count},
+        events::{proposal_variables, trigger_events},
+        lifecycle::{modify_proposal, now, refresh_status, set_status, winning_option},
+        settings,
+    },
+    types::{
+        event::EventTrigger,
+        on_win_action::{ActionResult, OnWinAction},
+        proposal::{Proposal, ProposalStatus},
+        space::Space,
+    },
+};
+
+thread_local! {
+    // Proposals with an execution in flight, so that a timer and a manual
+    // call can't both send the on-win transactions.
+    static EXECUTING: RefCell<HashSet<(u32, u32)>> = RefCell::new(HashSet::new());
+}
+
+/// Entry in `EXECUTING`, released when dropped. A trap during the execution
+/// drops the pending future, so the proposal can be executed again.
+struct ExecutionLock((u32, u32));
+
+impl ExecutionLock {
+    fn acquire(space_id: u32, proposal_id: u32) -> Option<Self> {
+        let key = (space_id, proposal_id);
+        EXECUTING
+            .with(|e| e.borrow_mut().insert(key))
+            .then_some(ExecutionLock(key))
+    }
+}
+
+impl Drop for ExecutionLock {
+    fn drop(&mut self) {
+        EXECUTING.with(|e| e.borrow_mut().remove(&self.0));
+    }
+}
+
+/// Queues a succeeded proposal behind the space's execution delay and
+/// schedules its automatic execution.
+pub fn queue(space: &Space, proposal_id: u32) -> Result<Proposal, String> {
+    let queued_at = now();
+    let eta = queued_at + space.execution_delay as u64;
+
+    let proposal = modify_proposal(space.id, proposal_id, |proposal| {
+        proposal.queued_at = Some(queued_at);
+        proposal.eta = Some(eta);
+        Ok(())
+    })?;
+
+    schedule(&proposal);
+
+    Ok(proposal)
+}
+
+/// Schedules the automatic execution of a queued proposal at its eta.
+/// Timers don't survive upgrades, so queued proposals are scheduled again
+/// after each one.
+pub fn schedule(proposal: &Proposal) {
+    let (space_id, proposal_id) = (proposal.space_id, proposal.id);
+    let delay = proposal.eta.map_or(0, |eta| eta.saturating_sub(now()));
+
+    ic_cdk_timers::set_timer(Duration::from_secs(delay), move || {
+        ic_cdk::spawn(async move {
+            let _ = execute(space_id, proposal_id).await;
+        });
+    });
+}
+
+/// Runs the on-win actions and settings changes of a queued proposal once its
+/// timelock expired.
+pub async fn execute(space_id: u32, proposal_id: u32) -> Result<Proposal, String> {
+    let proposal = refresh_status(space_id, proposal_id)?;
+
+    if proposal.status != ProposalStatus::Succeeded {
+        return Err("Only succeeded proposals can be executed".to_owned());
+    }
+
+    match proposal.eta {
+        Some(eta) if eta <= now() => {}
+        Some(_) => return Err("Proposal is still timelocked".to_owned()),
+        None => return Err("Proposal is not queued for execution".to_owned()),
+    }
+
+    let option = winning_option(&proposal)
+        .cloned()
+        .ok_or("Proposal has no winning option")?;
+
+    let lock = ExecutionLock::acquire(space_id, proposal_id)
+        .ok_or("Proposal is already being executed")?;
+
+    let results = run_actions(&option.on_win_actions, &proposal.execution_results).await;
+
+    drop(lock);
+
+    let mut error = results.iter().find_map(|r| r.error.clone());
+    let mut completed = error.is_none() && results.len() == option.on_win_actions.len();
+
+    // Sent transactions are recorded whatever happened to the proposal in the
+    // meantime, so their hashes are never lost.
+    modify_proposal(space_id, proposal_id, |proposal| {
+        proposal.execution_results = results;
+        Ok(())
+    })?;
+
+    // The guardian may have vetoed the proposal while the transactions were
+    // being sent, in which case its settings changes must not be applied.
+    let mut proposal = refresh_status(space_id, proposal_id)?;
+    if proposal.status != ProposalStatus::Succeeded {
+        return Err(format!(
+            "Proposal became {:?} during its execution",
+            proposal.status
+        ));
+    }
+
+    // Settings changes are applied last and only once every transaction went
+    // through, so a retry after a failed transaction doesn't apply them twice.
+    if completed {
+        if let Err(settings_error) = settings::apply(space_id, &option.settings_changes) {
+            error = Some(settings_error);
+            completed = false;
+        }
+    }
+
+    if completed {
+        proposal = set_status(space_id, proposal_id, ProposalStatus::Executed)?;
+    }
+
+    if completed {
+        let tx_hashes: Vec<String> = proposal
+            .execution_results
+            .iter()
+            .filter_map(|r| r.tx_hash.clone())
+            .collect();
+        let mut event_data = proposal_variables(&proposal);
+        event_data.insert("tx_hash", tx_hashes.join(","));
+
+        let _ = trigger_events(space_id, EventTrigger::ProposalExecuted, event_data).await;
+    }
+
+    match error {
+        Some(error) => Err(error),
+        None => Ok(proposal),
+    }
+}
+
+/// Sends the actions in order and stops at the first failure. Actions that
+/// already went through in an earlier attempt are not sent again.
+async fn run_actions(actions: &[OnWinAction], previous: &[ActionResult]) -> Vec<ActionResult> {
+    let mut results = Vec::new();
+    let mut nonces: HashMap<u64, U256> = HashMap::new();
+
+    for (index, action) in actions.iter().enumerate() {
+        let action_index = index as u32;
+
+        if let Some(done) = previous
+            .iter()
+            .find(|r| r.action_index == action_index && r.tx_hash.is_some())
+        {
+            results.push(done.clone());
+            continue;
+        }
+
+        match send_action(action, &mut nonces).await {
+            Ok(tx_hash) => results.push(ActionResult {
+                action_index,
+                tx_hash: Some(tx_hash),
+                error: None,
+            }),
+            Err(error) => {
+                results.push(ActionResult {
+                    action_index,
+                    tx_hash: None,
+                    error: Some(error),
+                });
+                break;
+            }
+        }
+    }
+
+    results
+}
+
+/// Signs and sends one action, returning its transaction hash. Nonces are
+/// fetched once per chain and then counted locally, since consecutive
+/// transactions are not yet visible to the RPC providers.
+async fn send_action(
+    action: &OnWinAction,
+    nonces: &mut HashMap<u64, U256>,
+) -> Result<String, String> {
+    let to = action
+        .target
+        .parse::<Address>()
+        .map_err(|_| "Invalid action target")?;
+    let data = hex::decode(action.calldata.trim_start_matches("0x"))
+        .map_err(|_| "Invalid action calldata")?;
+    let value =
+        U256::from_dec_str(&action.value.0.to_string()).map_err(|_| "Invalid action value")?;
+
+    let nonce = match nonces.get(&action.chain_id) {
+        Some(nonce) => *nonce,
+        None => transaction_count(action.chain_id).await?,
+    };
+
+    let raw_tx = sign_transaction(&to, value, &Bytes::from(data), action.chain_id, nonce).await?;
+    let tx_hash = call(action.chain_id, raw_tx).await?;
+
+    nonces.insert(action.chain_id, nonce + 1);
+
+    Ok(tx_hash)
+}
@@ -0,0 +1,510 @@
+use std::time::Duration;
+
+use candid::Nat;
+use ethers_core::utils::keccak256;
+use serde_json::json;
+
+use crate::{
+    get_space,
+    services::{
+        events::{proposal_variables, trigger_events},
+        execution,
+    },
+    types::{
+        event::EventTrigger,
+        proposal::{Proposal, ProposalStatus},
+        proposal_options::ProposalOption,
+        proposal_template::ProposalTemplate,
+        space::Space,
+    },
+    update_space_proposals,
+    utils::to_hex,
+};
+
+pub fn now() -> u64 {
+    // Convert nanoseconds to seconds
+    ic_cdk::api::time() / 1_000_000_000
+}
+
+/// Status of the proposal after applying the transitions that only depend on
+/// time. Results are settled separately by `finalize`, so an ended proposal
+/// stays `Closed` until then.
+pub fn current_status(proposal: &Proposal, now: u64) -> ProposalStatus {
+    // vote_start = 13s
+    // vote_end = 73s
+    // 0s -------------- 10s -------------- 13s ------------- 73s ------------- inf s
+    //           proposal created       voting available    voting finished
+    match proposal.status {
+        ProposalStatus::Pending if now > proposal.vote_end => ProposalStatus::Closed,
+        ProposalStatus::Pending if now >= proposal.vote_start => ProposalStatus::Active,
+        ProposalStatus::Active if now > proposal.vote_end => ProposalStatus::Closed,
+        status => status,
+    }
+}
+
+/// Resolves the voting window of a new proposal. Missing bounds fall back to
+/// the template's or else the space's `vote_delay` and `vote_duration`, and
+/// explicit windows must last between the space's minimum and maximum vote
+/// duration.
+pub fn voting_window(
+    space: &Space,
+    template: Option<&ProposalTemplate>,
+    now: u64,
+    vote_start: Option<u64>,
+    vote_end: Option<u64>,
+) -> Result<(u64, u64), String> {
+    let start = vote_start.unwrap_or(now + vote_delay(space, template) as u64);
+    let vote_duration = template
+        .and_then(|t| t.vote_duration)
+        .unwrap_or(space.vote_duration);
+    let end = vote_end.unwrap_or(start + vote_duration as u64);
+
+    if vote_start.is_none() && vote_end.is_none() {
+        return Ok((start, end));
+    }
+
+    if start < now {
+        return Err("Voting can't start in the past".to_owned());
+    }
+
+    if end <= start {
+        return Err("Voting must end after it starts".to_owned());
+    }
+
+    let duration = end - start;
+    if duration < space.min_vote_duration as u64 {
+        return Err("Voting window is shorter than the space allows".to_owned());
+    }
+
+    if space.max_vote_duration > 0 && duration > space.max_vote_duration as u64 {
+        return Err("Voting window is longer than the space allows".to_owned());
+    }
+
+    Ok((start, end))
+}
+
+pub fn vote_delay(space: &Space, template: Option<&ProposalTemplate>) -> u32 {
+    template
+        .and_then(|t| t.vote_delay)
+        .unwrap_or(space.vote_delay)
+}
+
+/// Returns the proposal with its time based status applied, for queries.
+pub fn with_current_status(mut proposal: Proposal) -> Proposal {
+    proposal.status = current_status(&proposal, now());
+    proposal
+}
+
+/// Option with the most voting power, if there is a single one.
+pub fn winning_option(proposal: &Proposal) -> Option<&ProposalOption> {
+    let totals: Vec<(&ProposalOption, Nat)> = proposal
+        .options
+        .iter()
+        .map(|option| (option, option_voting_power(option)))
+        .collect();
+
+    let max = totals.iter().map(|(_, power)| power.clone()).max()?;
+    let mut leaders = totals.iter().filter(|(_, power)| *power == max);
+    let (leader, _) = leaders.next()?;
+
+    if max == Nat::from(0 as u32) || leaders.next().is_some() {
+        return None;
+    }
+
+    Some(leader)
+}
+
+pub fn option_voting_power(option: &ProposalOption) -> Nat {
+    option
+        .votes
+        .iter()
+        .fold(Nat::from(0 as u32), |total, vote| {
+            total + vote.voting_power.clone()
+        })
+}
+
+/// Voting power cast on the proposal across all options.
+pub fn total_voting_power(proposal: &Proposal) -> Nat {
+    proposal
+        .options
+        .iter()
+        .fold(Nat::from(0 as u32), |total, option| {
+            total + option_voting_power(option)
+        })
+}
+
+pub fn tally(space: &Space, proposal: &Proposal) -> ProposalStatus {
+    if total_voting_power(proposal) < space.quorum {
+        ProposalStatus::QuorumNotMet
+    } else if winning_option(proposal).is_some() {
+        ProposalStatus::Succeeded
+    } else {
+        ProposalStatus::Defeated
+    }
+}
+
+/// Applies `change` to a stored proposal and persists it.
+pub fn modify_proposal(
+    space_id: u32,
+    proposal_id: u32,
+    change: impl FnOnce(&mut Proposal) -> Result<(), String>,
+) -> Result<Proposal, String> {
+    let space = get_space(space_id).ok_or("Invalid space id")?;
+    let mut proposals = space.proposals;
+    let proposal = proposals
+        .iter_mut()
+        .find(|p| p.id == proposal_id)
+        .ok_or("Invalid proposal id")?;
+
+    change(proposal)?;
+
+    let proposal = proposal.clone();
+    update_space_proposals(space_id, proposals);
+
+    Ok(proposal)
+}
+
+/// Moves the proposal to `status`, rejecting transitions the lifecycle does
+/// not allow.
+pub fn set_status(
+    space_id: u32,
+    proposal_id: u32,
+    status: ProposalStatus,
+) -> Result<Proposal, String> {
+    modify_proposal(space_id, proposal_id, |proposal| {
+        if !proposal.status.can_transition_to(status) {
+            return Err(format!(
+                "Proposal can't move from {:?} to {:?}",
+                proposal.status, status
+            ));
+        }
+
+        if status == ProposalStatus::Active {
+            proposal.content_hash = Some(content_hash(proposal));
+        }
+
+        proposal.status = status;
+        Ok(())
+    })
+}
+
+/// Title, description, mechanism, metadata and options can only change before
+/// voting opens, so that every voter votes on the same content.
+pub fn ensure_editable(proposal: &Proposal) -> Result<(), String> {
+    match proposal.status {
+        ProposalStatus::Draft | ProposalStatus::Pending => Ok(()),
+        _ => Err("Proposal can't be changed once voting has started".to_owned()),
+    }
+}
+
+/// Hex encoded keccak256 of the proposal content as JSON with sorted keys:
+/// `{"description","mechanism","metadata":{"attachments","discussion_url",
+/// "tags"},"options":[{"id","name","on_win_actions":[{"calldata","chain_id",
+/// "target","value"}],"settings_changes"}],"title"}`, with `value` as a
+/// decimal string and `settings_changes` in their serde JSON form.
+pub fn content_hash(proposal: &Proposal) -> String {
+    let options: Vec<serde_json::Value> = proposal
+        .options
+        .iter()
+        .map(|option| {
+            let actions: Vec<serde_json::Value> = option
+                .on_win_actions
+                .iter()
+                .map(|action| {
+                    json!({
+                        "target": action.target,
+                        "value": action.value.0.to_string(),
+                        "calldata": action.calldata,
+                        "chain_id": action.chain_id,
+                    })
+                })
+                .collect();
+
+            json!({
+                "id": option.id,
+                "name": option.name,
+                "on_win_actions": actions,
+                "settings_changes": option.settings_changes,
+            })
+        })
+        .collect();
+
+    let content = json!({
+        "title": proposal.title,
+        "description": proposal.description,
+        "mechanism": proposal.mechanism,
+        "metadata": {
+            "tags": proposal.metadata.tags,
+            "discussion_url": proposal.metadata.discussion_url,
+            "attachments": proposal.metadata.attachments,
+        },
+        "options": options,
+    });
+
+    to_hex(&keccak256(content.to_string()))
+}
+
+/// Persists the time based transitions of a proposal and returns it.
+pub fn refresh_status(space_id: u32, proposal_id: u32) -> Result<Proposal, String> {
+    let space = get_space(space_id).ok_or("Invalid space id")?;
+    let mut proposal = space
+        .proposals
+        .iter()
+        .find(|p| p.id == proposal_id)
+        .cloned()
+        .ok_or("Invalid proposal id")?;
+
+    let status = current_status(&proposal, now());
+
+    // Pending can skip straight to Closed when no call happened while the
+    // proposal was active, so walk through each step to keep them validated.
+    while proposal.status != status {
+        let next = match proposal.status {
+            ProposalStatus::Pending => ProposalStatus::Active,
+            _ => status,
+        };
+        proposal = set_status(space_id, proposal_id, next)?;
+    }
+
+    Ok(proposal)
+}
+
+/// Settles the result of a proposal whose voting has ended and notifies the
+/// space. Returns the settled proposal; calling it again is a no-op.
+pub async fn finalize(space_id: u32, proposal_id: u32) -> Result<Proposal, String> {
+    let proposal = refresh_status(space_id, proposal_id)?;
+
+    if proposal.status != ProposalStatus::Closed {
+        return Ok(proposal);
+    }
+
+    let space = get_space(space_id).ok_or("Invalid space id")?;
+    let mut proposal = set_status(space_id, proposal_id, tally(&space, &proposal))?;
+
+    if proposal.status == ProposalStatus::Succeeded {
+        proposal = execution::queue(&space, proposal_id)?;
+    }
+
+    let _ = trigger_events(
+        space_id,
+        EventTrigger::ProposalEnded,
+        proposal_variables(&proposal),
+    )
+    .await;
+
+    Ok(proposal)
+}
+
+/// Schedules the timers that open and close voting on a published proposal.
+/// Timers don't survive upgrades, so every call also re-checks the status.
+pub fn schedule_timers(proposal: &Proposal) {
+    let (space_id, proposal_id) = (proposal.space_id, proposal.id);
+    let now = now();
+    let start = proposal.vote_start.saturating_sub(now);
+    // Voting stays open through the last second of the window.
+    let end = (proposal.vote_end + 1).saturating_sub(now);
+
+    // Rescheduling after an upgrade must not announce an open vote again.
+    if proposal.vote_start >= now {
+        ic_cdk_timers::set_timer(Duration::from_secs(start), move || {
+            ic_cdk::spawn(async move {
+                let _ = notify_voting_started(space_id, proposal_id).await;
+            });
+        });
+    }
+
+    ic_cdk_timers::set_timer(Duration::from_secs(end), move || {
+        ic_cdk::spawn(async move {
+            let _ = finalize(space_id, proposal_id).await;
+        });
+    });
+
+    let lead_time = get_space(space_id).map_or(0, |s| s.ending_soon_lead_time as u64);
+    let ending_soon = proposal.vote_end.saturating_sub(lead_time);
+    if lead_time > 0 && ending_soon > now.max(proposal.vote_start) {
+        ic_cdk_timers::set_timer(Duration::from_secs(ending_soon - now), move || {
+            ic_cdk::spawn(async move {
+                let _ = notify_ending_soon(space_id, proposal_id).await;
+            });
+        });
+    }
+}
+
+async fn notify_voting_started(space_id: u32, proposal_id: u32) -> Result<(), String> {
+    notify_if_active(space_id, proposal_id, EventTrigger::VotingStarted).await
+}
+
+async fn notify_ending_soon(space_id: u32, proposal_id: u32) -> Result<(), String> {
+    notify_if_active(space_id, proposal_id, EventTrigger::ProposalEndingSoon).await
+}
+
+/// Notifies the space about a proposal in voting, unless it was cancelled or
+/// vetoed meanwhile.
+async fn notify_if_active(
+    space_id: u32,
+    proposal_id: u32,
+    event_trigger: EventTrigger,
+) -> Result<(), String> {
+    let proposal = refresh_status(space_id, proposal_id)?;
+
+    if proposal.status != ProposalStatus::Active {
+        return Ok(());
+    }
+
+    trigger_events(space_id, event_trigger, proposal_variables(&proposal))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{proposal_option_vote::ProposalOptionVote, space::InsertSpace};
+
+    fn space(quorum: u32) -> Space {
+        let settings = InsertSpace {
+            name: "Space".to_owned(),
+            icon_link: String::new(),
+            website_link: String::new(),
+            owner_address: "0xowner".to_owned(),
+            vote_delay: 10,
+            vote_duration: 100,
+            min_vote_duration: 50,
+            max_vote_duration: 200,
+            min_vote_role: None,
+            min_proposal_role: None,
+            min_vote_power: Nat::from(0_u32),
+            quorum: Nat::from(quorum),
+            allow_vote_change: false,
+            min_proposal_power: Nat::from(0_u32),
+            proposer_allowlist: Vec::new(),
+            guardian_address: None,
+            execution_delay: 0,
+            ending_soon_lead_time: 0,
+        };
+
+        Space::new(1, settings, Vec::new(), Vec::new(), Vec::new(), Vec::new())
+    }
+
+    fn option(id: u32, powers: &[u32]) -> ProposalOption {
+        ProposalOption {
+            id,
+            name: format!("Option {}", id),
+            proposal_id: 1,
+            on_win_actions: Vec::new(),
+            settings_changes: Vec::new(),
+            votes: powers
+                .iter()
+                .enumerate()
+                .map(|(index, power)| ProposalOptionVote {
+                    id: index as u32,
+                    user_address: format!("0x{}", index),
+                    vote_type: 0,
+                    timestamp: 0,
+                    signature: String::new(),
+                    voting_power: Nat::from(*power),
+                    option_id: id,
+                    delegated_power: Vec::new(),
+                    reason: None,
+                    message_timestamp: None,
+                })
+                .collect(),
+        }
+    }
+
+    fn proposal(status: ProposalStatus, options: Vec<ProposalOption>) -> Proposal {
+        Proposal {
+            id: 1,
+            title: "Proposal".to_owned(),
+            description: String::new(),
+            date_created: 0,
+            mechanism: 0,
+            space_id: 1,
+            options,
+            status,
+            vote_start: 10,
+            vote_end: 110,
+            author: "0xauthor".to_owned(),
+            signature: String::new(),
+            queued_at: None,
+            eta: None,
+            execution_results: Vec::new(),
+            content_hash: None,
+            template_id: None,
+            metadata: Default::default(),
+            snapshot_blocks: None,
+        }
+    }
+
+    #[test]
+    fn status_follows_the_voting_window() {
+        let pending = proposal(ProposalStatus::Pending, Vec::new());
+        assert_eq!(current_status(&pending, 9), ProposalStatus::Pending);
+        assert_eq!(current_status(&pending, 10), ProposalStatus::Active);
+        assert_eq!(current_status(&pending, 110), ProposalStatus::Active);
+        assert_eq!(current_status(&pending, 111), ProposalStatus::Closed);
+
+        let active = proposal(ProposalStatus::Active, Vec::new());
+        assert_eq!(current_status(&active, 111), ProposalStatus::Closed);
+
+        for status in [ProposalStatus::Draft, ProposalStatus::Cancelled] {
+            assert_eq!(current_status(&proposal(status, Vec::new()), 111), status);
+        }
+    }
+
+    #[test]
+    fn voting_window_defaults_to_space_settings() {
+        assert_eq!(
+            voting_window(&space(0), None, 1_000, None, None),
+            Ok((1_010, 1_110))
+        );
+    }
+
+    #[test]
+    fn voting_window_respects_space_bounds() {
+        let space = space(0);
+        let window = |start, end| voting_window(&space, None, 1_000, start, end);
+
+        assert_eq!(window(Some(1_000), Some(1_050)), Ok((1_000, 1_050)));
+        assert_eq!(window(None, Some(1_210)), Ok((1_010, 1_210)));
+        assert!(window(Some(999), Some(1_100)).is_err());
+        assert!(window(Some(1_100), Some(1_100)).is_err());
+        assert!(window(Some(1_000), Some(1_049)).is_err());
+        assert!(window(Some(1_000), Some(1_201)).is_err());
+    }
+
+    #[test]
+    fn winning_option_needs_a_single_leader() {
+        let leader = proposal(
+            ProposalStatus::Closed,
+            vec![option(1, &[3, 4]), option(2, &[5])],
+        );
+        assert_eq!(winning_option(&leader).map(|o| o.id), Some(1));
+
+        let tie = proposal(
+            ProposalStatus::Closed,
+            vec![option(1, &[5]), option(2, &[5])],
+        );
+        assert!(winning_option(&tie).is_none());
+
+        let no_votes = proposal(ProposalStatus::Closed, vec![option(1, &[]), option(2, &[])]);
+        assert!(winning_option(&no_votes).is_none());
+    }
+
+    #[test]
+    fn tally_checks_quorum_before_the_winner() {
+        let leader = proposal(
+            ProposalStatus::Closed,
+            vec![option(1, &[6]), option(2, &[4])],
+        );
+        let tie = proposal(
+            ProposalStatus::Closed,
+            vec![option(1, &[5]), option(2, &[5])],
+        );
+
+        assert_eq!(tally(&space(10), &leader), ProposalStatus::Succeeded);
+        assert_eq!(tally(&space(11), &leader), ProposalStatus::QuorumNotMet);
+        assert_eq!(tally(&space(10), &tie), ProposalStatus::Defeated);
+        assert_eq!(tally(&space(11), &tie), ProposalStatus::QuorumNotMet);
+    }
+}
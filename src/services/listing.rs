@@ -0,0 +1,159 @@
+use ic_cdk_macros::query;
+
+use super::lifecycle::{option_voting_power, with_current_status};
+use crate::{
+    get_proposal, get_space,
+    types::{
+        proposal::{Proposal, ProposalFilter, ProposalPage, ProposalSummary},
+        proposal_option_vote::{ProposalOptionVote, VoteCursor, VoteFilter, VotePage},
+        proposal_options::{ProposalOption, ProposalOptionSummary},
+    },
+    MAX_PAGE_SIZE,
+};
+
+/// Returns up to `limit` proposal summaries of a space matching `filter`,
+/// ordered by id and starting after the proposal id given as `cursor`.
+#[query]
+fn list_proposals(
+    space_id: u32,
+    cursor: Option<u32>,
+    limit: u32,
+    filter: ProposalFilter,
+) -> Option<ProposalPage> {
+    let mut proposals = get_space(space_id)?.proposals;
+    proposals.sort_by_key(|p| p.id);
+
+    let author = filter.author.map(|a| a.to_lowercase());
+    let limit = limit.clamp(1, MAX_PAGE_SIZE) as usize;
+
+    let mut summaries: Vec<ProposalSummary> = proposals
+        .into_iter()
+        .filter(|p| cursor.is_none_or(|cursor| p.id > cursor))
+        .map(with_current_status)
+        .filter(|p| filter.status.is_none_or(|status| p.status == status))
+        .filter(|p| {
+            author
+                .as_ref()
+                .is_none_or(|author| p.author.eq_ignore_ascii_case(author))
+        })
+        .filter(|p| {
+            filter
+                .created_after
+                .is_none_or(|after| p.date_created >= after)
+        })
+        .filter(|p| {
+            filter
+                .created_before
+                .is_none_or(|before| p.date_created <= before)
+        })
+        .take(limit + 1)
+        .map(|p| proposal_summary(&p))
+        .collect();
+
+    let next_cursor = if summaries.len() > limit {
+        summaries.truncate(limit);
+        summaries.last().map(|p| p.id)
+    } else {
+        None
+    };
+
+    Some(ProposalPage {
+        proposals: summaries,
+        next_cursor,
+    })
+}
+
+#[query]
+fn get_proposal_summary(space_id: u32, proposal_id: u32) -> Option<ProposalSummary> {
+    get_proposal(space_id, proposal_id).map(|p| proposal_summary(&p))
+}
+
+#[query]
+fn list_proposal_options(space_id: u32, proposal_id: u32) -> Option<Vec<ProposalOptionSummary>> {
+    let proposal = get_proposal(space_id, proposal_id)?;
+    Some(proposal.options.iter().map(option_summary).collect())
+}
+
+/// Returns up to `limit` votes of a proposal matching `filter`, ordered by
+/// option and vote id and starting after `cursor`.
+#[query]
+fn list_votes(
+    space_id: u32,
+    proposal_id: u32,
+    cursor: Option<VoteCursor>,
+    limit: u32,
+    filter: VoteFilter,
+) -> Option<VotePage> {
+    let mut options = get_proposal(space_id, proposal_id)?.options;
+    options.sort_by_key(|o| o.id);
+
+    let address = filter.address.map(|a| a.to_lowercase());
+    let limit = limit.clamp(1, MAX_PAGE_SIZE) as usize;
+
+    let mut votes: Vec<(VoteCursor, ProposalOptionVote)> = options
+        .into_iter()
+        .filter(|o| filter.option_id.is_none_or(|id| o.id == id))
+        .flat_map(|o| {
+            let mut votes = o.votes;
+            votes.sort_by_key(|v| v.id);
+            votes.into_iter().map(move |v| {
+                let position = VoteCursor {
+                    option_id: o.id,
+                    vote_id: v.id,
+                };
+                (position, v)
+            })
+        })
+        .filter(|(position, _)| cursor.is_none_or(|cursor| *position > cursor))
+        .filter(|(_, v)| {
+            address
+                .as_ref()
+                .is_none_or(|address| v.user_address.eq_ignore_ascii_case(address))
+        })
+        .filter(|(_, v)| {
+            filter
+                .min_power
+                .as_ref()
+                .is_none_or(|min_power| v.voting_power >= *min_power)
+        })
+        .take(limit + 1)
+        .collect();
+
+    let next_cursor = if votes.len() > limit {
+        votes.truncate(limit);
+        votes.last().map(|(position, _)| *position)
+    } else {
+        None
+    };
+
+    Some(VotePage {
+        votes: votes.into_iter().map(|(_, v)| v).collect(),
+        next_cursor,
+    })
+}
+
+pub fn proposal_summary(proposal: &Proposal) -> ProposalSummary {
+    ProposalSummary {
+        id: proposal.id,
+        space_id: proposal.space_id,
+        title: proposal.title.clone(),
+        mechanism: proposal.mechanism,
+        status: proposal.status,
+        date_created: proposal.date_created,
+        vote_start: proposal.vote_start,
+        vote_end: proposal.vote_end,
+        author: proposal.author.clone(),
+        template_id: proposal.template_id,
+        metadata: proposal.metadata.clone(),
+        options: proposal.options.iter().map(option_summary).collect(),
+    }
+}
+
+fn option_summary(option: &ProposalOption) -> ProposalOptionSummary {
+    ProposalOptionSummary {
+        id: option.id,
+        name: option.name.clone(),
+        vote_count: option.votes.len() as u32,
+        voting_power: option_voting_power(option),
+    }
+}
@@ -1,3 +1,15 @@
 pub mod eth_rpc;
 pub mod voting;
-pub mod events;
\ No newline at end of file
+pub mod events;
+pub mod multicall;
+pub mod delegation;
+pub mod lifecycle;
+pub mod execution;
+pub mod comments;
+pub mod templates;
+pub mod roles;
+pub mod ownership;
+pub mod settings;
+pub mod listing;
+pub mod vote_history;
+pub mod subscriptions;
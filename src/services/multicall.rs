@@ -0,0 +1,191 @@
+use ethers_core::abi::{self, Address, ParamType, Token};
+
+use super::eth_rpc::eth_call;
+use crate::utils::to_hex;
+
+/// Multicall3 is deployed at the same address on every supported chain.
+pub const MULTICALL3_ADDRESS: &str = "0xcA11bde05977b3631167028862bE2a173976CA11";
+
+/// `aggregate3((address,bool,bytes)[])`
+const AGGREGATE3_SELECTOR: [u8; 4] = [0x82, 0xad, 0x56, 0xcb];
+
+/// Rough per-call allowance for the ABI-encoded `(bool, bytes)` result,
+/// including the JSON-RPC hex encoding overhead.
+const RESPONSE_BYTES_PER_CALL: u64 = 512;
+const RESPONSE_BYTES_BASE: u64 = 1024;
+// Keeps each request and response well below the outcall size limits
+const MAX_CALLS_PER_BATCH: usize = 200;
+
+pub struct Call {
+    pub target: String,
+    pub data: String,
+}
+
+/// Executes `calls` on `chain_id` through Multicall3, in one `eth_call` per
+/// `MAX_CALLS_PER_BATCH` calls.
+///
+/// Each call is sent with `allowFailure = true`, so one reverting call doesn't
+/// revert the others. The result at index `i` is the hex encoded return data of
+/// `calls[i]`, or an error if that call reverted; it is up to the caller whether
+/// such an error fails the whole batch.
+pub async fn aggregate3(
+    chain_id: u64,
+    calls: &[Call],
+    block_height: Option<String>,
+) -> Result<Vec<Result<String, String>>, String> {
+    let mut results = Vec::with_capacity(calls.len());
+
+    for batch in calls.chunks(MAX_CALLS_PER_BATCH) {
+        let data = encode_aggregate3(batch)?;
+        let max_response_bytes = RESPONSE_BYTES_BASE + RESPONSE_BYTES_PER_CALL * batch.len() as u64;
+
+        let response = eth_call(
+            chain_id,
+            MULTICALL3_ADDRESS.to_owned(),
+            data,
+            block_height.clone(),
+            max_response_bytes,
+        )
+        .await?;
+
+        results.extend(decode_aggregate3(&response, batch.len())?);
+    }
+
+    Ok(results)
+}
+
+fn encode_aggregate3(calls: &[Call]) -> Result<String, String> {
+    let mut tokens = Vec::with_capacity(calls.len());
+
+    for call in calls {
+        let target = call
+            .target
+            .parse::<Address>()
+            .map_err(|_| format!("Invalid contract address {}", call.target))?;
+        let data = hex::decode(call.data.trim_start_matches("0x"))
+            .map_err(|_| format!("Invalid call data for {}", call.target))?;
+
+        tokens.push(Token::Tuple(vec![
+            Token::Address(target),
+            Token::Bool(true),
+            Token::Bytes(data),
+        ]));
+    }
+
+    let mut encoded = AGGREGATE3_SELECTOR.to_vec();
+    encoded.extend(abi::encode(&[Token::Array(tokens)]));
+
+    Ok(to_hex(&encoded))
+}
+
+fn decode_aggregate3(
+    response: &str,
+    expected: usize,
+) -> Result<Vec<Result<String, String>>, String> {
+    let bytes = hex::decode(response.trim_start_matches("0x"))
+        .map_err(|_| "Unable to parse response from multicall".to_owned())?;
+
+    let result_type = ParamType::Array(Box::new(ParamType::Tuple(vec![
+        ParamType::Bool,
+        ParamType::Bytes,
+    ])));
+
+    let decoded = abi::decode(&[result_type], &bytes)
+        .map_err(|_| "Unable to decode response from multicall".to_owned())?;
+
+    let results = match decoded.into_iter().next() {
+        Some(Token::Array(results)) => results,
+        _ => return Err("Unable to decode response from multicall".to_owned()),
+    };
+
+    if results.len() != expected {
+        return Err("Multicall returned an unexpected number of results".to_owned());
+    }
+
+    Ok(results
+        .into_iter()
+        .map(|result| match result {
+            Token::Tuple(values) => match values.as_slice() {
+                [Token::Bool(true), Token::Bytes(data)] => Ok(to_hex(data)),
+                _ => Err("Strategy call reverted".to_owned()),
+            },
+            _ => Err("Unable to decode response from multicall".to_owned()),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers_core::utils::id;
+
+    fn word(value: u64) -> String {
+        format!("{:064x}", value)
+    }
+
+    #[test]
+    fn selector_matches_signature() {
+        assert_eq!(
+            id("aggregate3((address,bool,bytes)[])"),
+            AGGREGATE3_SELECTOR
+        );
+    }
+
+    #[test]
+    fn encodes_calls_with_allow_failure() {
+        let calls = [Call {
+            target: "0x0000000000000000000000000000000000000001".to_owned(),
+            data: "0x12345678".to_owned(),
+        }];
+
+        let expected = [
+            "0x82ad56cb".to_owned(),
+            word(0x20), // offset of the array
+            word(1),    // number of calls
+            word(0x20), // offset of the first call
+            word(1),    // target
+            word(1),    // allowFailure
+            word(0x60), // offset of the call data
+            word(4),    // call data length
+            format!("{:0<64}", "12345678"),
+        ]
+        .concat();
+
+        assert_eq!(encode_aggregate3(&calls).unwrap(), expected);
+    }
+
+    #[test]
+    fn rejects_invalid_target() {
+        let calls = [Call {
+            target: "not an address".to_owned(),
+            data: "0x".to_owned(),
+        }];
+
+        assert!(encode_aggregate3(&calls).is_err());
+    }
+
+    #[test]
+    fn decodes_results_and_reverts() {
+        let response = [
+            "0x".to_owned(),
+            word(0x20), // offset of the array
+            word(2),    // number of results
+            word(0x40), // offset of the first result
+            word(0xc0), // offset of the second result
+            word(1),    // success
+            word(0x40), // offset of the return data
+            word(0x20), // return data length
+            word(5),
+            word(0),    // success
+            word(0x40), // offset of the return data
+            word(0),    // return data length
+        ]
+        .concat();
+
+        let results = decode_aggregate3(&response, 2).unwrap();
+
+        assert_eq!(results[0], Ok(format!("0x{}", word(5))));
+        assert_eq!(results[1], Err("Strategy call reverted".to_owned()));
+        assert!(decode_aggregate3(&response, 3).is_err());
+    }
+}
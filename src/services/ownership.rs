@@ -0,0 +1,150 @@
+use ethers_core::types::Address;
+use ic_cdk::update;
+
+use super::roles;
+use crate::{
+    get_space,
+    types::{
+        role::Authorization,
+        space::{AcceptOwnershipData, Ownership, Space},
+    },
+    update_space_ownership,
+    utils::{normalize_address, verify_signature},
+};
+
+/// Starts handing the space to `new_owner`, who has to accept with
+/// `accept_ownership`. Passing `None` withdraws a pending transfer.
+#[update]
+fn propose_owner(
+    space_id: u32,
+    new_owner: Option<String>,
+    auth: Authorization,
+) -> Result<Space, String> {
+    let space = get_space(space_id).ok_or("Invalid space id")?;
+
+    let new_owner = match new_owner {
+        Some(address) => {
+            let address = address
+                .parse::<Address>()
+                .map_err(|_| "Invalid owner address")?;
+            Some(normalize_address(&address))
+        }
+        None => None,
+    };
+
+    roles::authorize_signers(
+        &space,
+        "propose_owner",
+        &(&new_owner,),
+        &auth,
+        |signer, cosigners| require_owner(&space, signer, cosigners),
+    )?;
+
+    let ownership = Ownership {
+        pending_owner: new_owner,
+        ..space.ownership.clone()
+    };
+
+    Ok(update_space_ownership(
+        space_id,
+        space.owner_address,
+        ownership,
+    ))
+}
+
+/// Completes a transfer, signed by the pending owner. The new owner takes the
+/// place of `owner_address`; co-owners and threshold stay as they are.
+#[update]
+fn accept_ownership(data: AcceptOwnershipData) -> Result<Space, String> {
+    let message = &data.message;
+    let signer = verify_signature(message, &data.signature, &message.address)?;
+    let signer = normalize_address(&signer);
+    let space = get_space(message.space_id).ok_or("Invalid space id")?;
+
+    match space.ownership.pending_owner {
+        Some(ref pending) if pending.eq_ignore_ascii_case(&signer) => {}
+        _ => return Err("Address has no pending ownership transfer".to_owned()),
+    }
+
+    roles::consume_timestamp(space.id, &signer, message.timestamp)?;
+
+    let co_owners: Vec<String> = space
+        .ownership
+        .co_owners
+        .iter()
+        .filter(|o| !o.eq_ignore_ascii_case(&signer))
+        .cloned()
+        .collect();
+
+    // A co-owner taking over leaves one owner less, keep the threshold reachable.
+    let threshold = space.ownership.threshold.min(co_owners.len() as u32 + 1);
+    let ownership = Ownership {
+        pending_owner: None,
+        co_owners,
+        threshold,
+    };
+
+    Ok(update_space_ownership(space.id, signer, ownership))
+}
+
+/// Sets the co-owners of the space and how many owner signatures admin
+/// actions need, e.g. 3 of 5 for a DAO's core team.
+#[update]
+fn set_owners(
+    space_id: u32,
+    co_owners: Vec<String>,
+    threshold: u32,
+    auth: Authorization,
+) -> Result<Space, String> {
+    let space = get_space(space_id).ok_or("Invalid space id")?;
+
+    let mut owners: Vec<String> = Vec::new();
+    for address in co_owners.iter() {
+        let address = address
+            .parse::<Address>()
+            .map_err(|_| "Invalid owner address")?;
+        let address = normalize_address(&address);
+
+        if !address.eq_ignore_ascii_case(&space.owner_address) && !owners.contains(&address) {
+            owners.push(address);
+        }
+    }
+
+    if threshold == 0 || threshold as usize > owners.len() + 1 {
+        return Err("Threshold must be between 1 and the number of owners".to_owned());
+    }
+
+    roles::authorize_signers(
+        &space,
+        "set_owners",
+        &(&co_owners, threshold),
+        &auth,
+        |signer, cosigners| require_owner(&space, signer, cosigners),
+    )?;
+
+    let ownership = Ownership {
+        pending_owner: space.ownership.pending_owner.clone(),
+        co_owners: owners,
+        threshold,
+    };
+
+    Ok(update_space_ownership(
+        space_id,
+        space.owner_address,
+        ownership,
+    ))
+}
+
+/// Ownership changes need `owner_address` itself, or the owner threshold
+/// when the space has one.
+fn require_owner(space: &Space, signer: &str, cosigners: &[String]) -> Result<(), String> {
+    if space.ownership.threshold > 1 {
+        return roles::require_owners(space, signer, cosigners);
+    }
+
+    if !space.owner_address.eq_ignore_ascii_case(signer) {
+        return Err("Only the space owner can change its owners".to_owned());
+    }
+
+    Ok(())
+}
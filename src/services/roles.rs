@@ -0,0 +1,310 @@
+use ethers_core::types::Address;
+use ic_cdk::{query, update};
+use serde::Serialize;
+
+use crate::{
+    get_space,
+    types::{
+        role::{Authorization, Cosignature, MemberKey, RoleData, SpaceMember, SpaceRole},
+        space::Space,
+    },
+    utils::{normalize_address, verify_signature},
+    MEMBERS,
+};
+
+/// Message signed for an `Authorization`. Fields are in alphabetical order so
+/// the JSON matches what clients build with sorted keys.
+#[derive(Serialize)]
+struct AuthorizedCall<'a, T: Serialize> {
+    address: &'a str,
+    args: &'a T,
+    method: &'a str,
+    space_id: u32,
+    timestamp: u64,
+}
+
+/// Replaces the roles of an address in a space. Only Admins may assign
+/// roles; the space owners are always Admins.
+#[update]
+fn set_member_roles(data: RoleData) -> Result<SpaceMember, String> {
+    let message = data.message;
+    let signer = verify_signature(&message, &data.signature, &message.address)?;
+    let signer = normalize_address(&signer);
+    let space = get_space(message.space_id).ok_or("Invalid space id")?;
+
+    let cosigners = verify_cosignatures(&message, &data.cosignatures)?;
+    require_admin(&space, &signer, &cosigners)?;
+
+    let member = message
+        .member
+        .parse::<Address>()
+        .map_err(|_| "Invalid member address")?;
+    let member = normalize_address(&member);
+
+    if message
+        .roles
+        .iter()
+        .any(|r| matches!(r, SpaceRole::Custom(name) if name.trim().is_empty()))
+    {
+        return Err("Custom roles must have a name".to_owned());
+    }
+
+    consume_timestamp(space.id, &signer, message.timestamp)?;
+
+    let mut roles: Vec<SpaceRole> = Vec::new();
+    for role in message.roles {
+        if !roles.contains(&role) {
+            roles.push(role);
+        }
+    }
+
+    let key = MemberKey {
+        space_id: space.id,
+        address: member.clone(),
+    };
+
+    MEMBERS.with(|m| {
+        let mut members = m.borrow_mut();
+        let last_timestamp = members.get(&key).map_or(0, |m| m.last_timestamp);
+        let record = SpaceMember {
+            space_id: space.id,
+            address: member,
+            roles,
+            last_timestamp,
+        };
+
+        members.insert(key, record.clone());
+        Ok(record)
+    })
+}
+
+/// Members of a space with at least one assigned role. The owner's implicit
+/// Admin role is not listed.
+#[query]
+fn get_space_members(space_id: u32) -> Vec<SpaceMember> {
+    space_members(space_id)
+        .into_iter()
+        .filter(|m| !m.roles.is_empty())
+        .collect()
+}
+
+#[query]
+fn get_member_roles(space_id: u32, address: String) -> Vec<SpaceRole> {
+    match get_space(space_id) {
+        Some(space) => roles_of(&space, &address),
+        None => Vec::new(),
+    }
+}
+
+/// Roles of `address` in the space, including the owners' implicit Admin.
+pub fn roles_of(space: &Space, address: &str) -> Vec<SpaceRole> {
+    let key = MemberKey {
+        space_id: space.id,
+        address: address.to_lowercase(),
+    };
+
+    let mut roles = MEMBERS.with(|m| m.borrow().get(&key).map_or(Vec::new(), |m| m.roles));
+
+    if is_owner(space, address) && !roles.contains(&SpaceRole::Admin) {
+        roles.push(SpaceRole::Admin);
+    }
+
+    roles
+}
+
+/// `owner_address` and the co-owners of the space.
+pub fn owners(space: &Space) -> Vec<String> {
+    let mut owners = vec![space.owner_address.to_lowercase()];
+    owners.extend(space.ownership.co_owners.iter().map(|o| o.to_lowercase()));
+    owners
+}
+
+pub fn is_owner(space: &Space, address: &str) -> bool {
+    owners(space)
+        .iter()
+        .any(|o| o.eq_ignore_ascii_case(address))
+}
+
+/// Spaces with an owner threshold above one need that many owners among the
+/// signer and cosigners for admin actions; single Admins can't act alone.
+/// Otherwise the signer needs the Admin role.
+pub fn require_admin(space: &Space, signer: &str, cosigners: &[String]) -> Result<(), String> {
+    if space.ownership.threshold <= 1 {
+        return require_role(space, signer, &SpaceRole::Admin);
+    }
+
+    require_owners(space, signer, cosigners)
+}
+
+/// Requires `threshold` distinct owners among the signer and cosigners.
+pub fn require_owners(space: &Space, signer: &str, cosigners: &[String]) -> Result<(), String> {
+    let threshold = space.ownership.threshold.max(1) as usize;
+    let mut signers: Vec<&str> = Vec::new();
+
+    for address in std::iter::once(signer).chain(cosigners.iter().map(String::as_str)) {
+        if is_owner(space, address) && !signers.iter().any(|s| s.eq_ignore_ascii_case(address)) {
+            signers.push(address);
+        }
+    }
+
+    if signers.len() < threshold {
+        return Err(format!(
+            "{} of {} owner signatures required",
+            threshold,
+            owners(space).len()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Verifies that every cosignature signs `message` and returns the
+/// normalized cosigner addresses.
+pub fn verify_cosignatures<T: Serialize>(
+    message: &T,
+    cosignatures: &[Cosignature],
+) -> Result<Vec<String>, String> {
+    cosignatures
+        .iter()
+        .map(|c| verify_signature(message, &c.signature, &c.address).map(|a| normalize_address(&a)))
+        .collect()
+}
+
+pub fn has_role(space: &Space, address: &str, required: &SpaceRole) -> bool {
+    roles_of(space, address)
+        .iter()
+        .any(|role| role.grants(required))
+}
+
+pub fn require_role(space: &Space, address: &str, required: &SpaceRole) -> Result<(), String> {
+    if has_role(space, address, required) {
+        Ok(())
+    } else {
+        Err(format!("{:?} role required", required))
+    }
+}
+
+/// Verifies the signatures of a call to `method` with `args`, runs `check`
+/// with the signer's and cosigners' addresses and consumes the
+/// authorization's timestamp. Returns the normalized signer address.
+pub fn authorize_signers<T: Serialize>(
+    space: &Space,
+    method: &str,
+    args: &T,
+    auth: &Authorization,
+    check: impl FnOnce(&str, &[String]) -> Result<(), String>,
+) -> Result<String, String> {
+    let message = AuthorizedCall {
+        address: &auth.address,
+        args,
+        method,
+        space_id: space.id,
+        timestamp: auth.timestamp,
+    };
+
+    let signer = verify_signature(&message, &auth.signature, &auth.address)?;
+    let signer = normalize_address(&signer);
+    let cosigners = verify_cosignatures(&message, &auth.cosignatures)?;
+
+    check(&signer, &cosigners)?;
+    consume_timestamp(space.id, &signer, auth.timestamp)?;
+
+    Ok(signer)
+}
+
+/// Like `authorize_signers`, for checks that only look at the signer.
+pub fn authorize<T: Serialize>(
+    space: &Space,
+    method: &str,
+    args: &T,
+    auth: &Authorization,
+    check: impl FnOnce(&str) -> Result<(), String>,
+) -> Result<String, String> {
+    authorize_signers(space, method, args, auth, |signer, _| check(signer))
+}
+
+/// Like `authorize`, requiring the signer to hold `required`. Admin actions
+/// go through `require_admin`, so they honour the owner threshold.
+pub fn authorize_role<T: Serialize>(
+    space: &Space,
+    method: &str,
+    args: &T,
+    auth: &Authorization,
+    required: SpaceRole,
+) -> Result<String, String> {
+    authorize_signers(space, method, args, auth, |signer, cosigners| {
+        if required == SpaceRole::Admin {
+            require_admin(space, signer, cosigners)
+        } else {
+            require_role(space, signer, &required)
+        }
+    })
+}
+
+/// Number of addresses with at least one assigned role in the space.
+pub fn member_count(space_id: u32) -> u32 {
+    space_members(space_id)
+        .iter()
+        .filter(|m| !m.roles.is_empty())
+        .count() as u32
+}
+
+/// Drops the members of a deleted space.
+pub fn remove_space_members(space_id: u32) {
+    let keys: Vec<MemberKey> = space_members(space_id)
+        .into_iter()
+        .map(|m| MemberKey {
+            space_id,
+            address: m.address,
+        })
+        .collect();
+
+    MEMBERS.with(|m| {
+        let mut members = m.borrow_mut();
+        for key in keys {
+            members.remove(&key);
+        }
+    });
+}
+
+/// Records `timestamp` as the last request of `address`, rejecting it unless
+/// it is newer than the previous one.
+pub fn consume_timestamp(space_id: u32, address: &str, timestamp: u64) -> Result<(), String> {
+    let key = MemberKey {
+        space_id,
+        address: address.to_owned(),
+    };
+
+    MEMBERS.with(|m| {
+        let mut members = m.borrow_mut();
+        let mut member = members.get(&key).unwrap_or(SpaceMember {
+            space_id,
+            address: address.to_owned(),
+            roles: Vec::new(),
+            last_timestamp: 0,
+        });
+
+        if member.last_timestamp >= timestamp {
+            return Err("Request timestamp is outdated".to_owned());
+        }
+
+        member.last_timestamp = timestamp;
+        members.insert(key, member);
+        Ok(())
+    })
+}
+
+fn space_members(space_id: u32) -> Vec<SpaceMember> {
+    let start = MemberKey {
+        space_id,
+        address: String::new(),
+    };
+
+    MEMBERS.with(|m| {
+        m.borrow()
+            .range(start..)
+            .take_while(|(key, _)| key.space_id == space_id)
+            .map(|(_, member)| member)
+            .collect()
+    })
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use crate::{
+    get_space, store_space,
+    types::{
+        event::{Event, EventData, EventTrigger},
+        evm_strategy::EvmStrategy,
+        settings_change::SettingsChange,
+        space::{InsertSpace, Space},
+        strategy::{Strategy, StrategyData},
+    },
+};
+
+use super::events::{notify, strategy_variables};
+use super::voting::invalidate_voting_power_cache;
+
+// Changes to space settings, shared by the admin endpoints and by proposals
+// carrying settings changes. They only touch the given space; callers store
+// it once everything succeeded.
+
+pub fn update_space(space: &mut Space, settings: InsertSpace) -> Result<(), String> {
+    if !settings
+        .owner_address
+        .eq_ignore_ascii_case(&space.owner_address)
+    {
+        return Err("Space owner can only change through an ownership transfer".to_owned());
+    }
+
+    let mut new_space = Space::new(
+        space.id,
+        settings,
+        std::mem::take(&mut space.proposals),
+        std::mem::take(&mut space.strategies),
+        std::mem::take(&mut space.events),
+        std::mem::take(&mut space.templates),
+    );
+    new_space.ownership = space.ownership.clone();
+    *space = new_space;
+
+    Ok(())
+}
+
+pub fn insert_evm_strategy(
+    space: &mut Space,
+    name: String,
+    description: String,
+    evm_strategy: EvmStrategy,
+) -> Strategy {
+    let id = space.strategies.iter().map(|s| s.id).max().unwrap_or(0) + 1;
+    let strategy = Strategy {
+        id,
+        name,
+        description,
+        space_id: space.id,
+        data: StrategyData::Evm(evm_strategy),
+    };
+
+    space.strategies.push(strategy.clone());
+    strategy
+}
+
+pub fn update_evm_strategy(
+    space: &mut Space,
+    strategy_id: u32,
+    name: String,
+    description: String,
+    evm_strategy: EvmStrategy,
+) -> Result<Strategy, String> {
+    let strategy = space
+        .strategies
+        .iter_mut()
+        .find(|s| s.id == strategy_id)
+        .ok_or("Invalid strategy id")?;
+
+    *strategy = Strategy {
+        id: strategy_id,
+        name,
+        description,
+        space_id: space.id,
+        data: StrategyData::Evm(evm_strategy),
+    };
+
+    Ok(strategy.clone())
+}
+
+pub fn delete_strategy(space: &mut Space, strategy_id: u32) -> Result<Strategy, String> {
+    let index = space
+        .strategies
+        .iter()
+        .position(|s| s.id == strategy_id)
+        .ok_or("Invalid strategy id")?;
+
+    Ok(space.strategies.remove(index))
+}
+
+pub fn insert_event(
+    space: &mut Space,
+    event_trigger: EventTrigger,
+    data: EventData,
+) -> Result<Event, String> {
+    if let EventData::Webhook(ref webhook) = data {
+        webhook.check()?;
+    }
+
+    let event = Event {
+        event_trigger,
+        space_id: space.id,
+        data,
+    };
+
+    space.events.push(event.clone());
+    Ok(event)
+}
+
+/// Applies the settings changes of an executed proposal in order. Either all
+/// of them are stored or, if one fails, none.
+pub fn apply(space_id: u32, changes: &[SettingsChange]) -> Result<(), String> {
+    let mut space = get_space(space_id).ok_or("Invalid space id")?;
+    let mut changed_strategies = Vec::new();
+    let mut notifications = Vec::new();
+
+    for change in changes.iter().cloned() {
+        match change {
+            SettingsChange::UpdateSpace(settings) => {
+                update_space(&mut space, settings)?;
+                notifications.push((EventTrigger::SpaceUpdated, HashMap::new()));
+            }
+            SettingsChange::InsertEvmStrategy {
+                name,
+                description,
+                evm_strategy,
+            } => {
+                let strategy = insert_evm_strategy(&mut space, name, description, evm_strategy);
+                notifications.push((
+                    EventTrigger::StrategyChanged,
+                    strategy_variables(&strategy, "inserted"),
+                ));
+            }
+            SettingsChange::UpdateEvmStrategy {
+                strategy_id,
+                name,
+                description,
+                evm_strategy,
+            } => {
+                let strategy =
+                    update_evm_strategy(&mut space, strategy_id, name, description, evm_strategy)?;
+                changed_strategies.push(strategy_id);
+                notifications.push((
+                    EventTrigger::StrategyChanged,
+                    strategy_variables(&strategy, "updated"),
+                ));
+            }
+            SettingsChange::DeleteStrategy { strategy_id } => {
+                let strategy = delete_strategy(&mut space, strategy_id)?;
+                changed_strategies.push(strategy_id);
+                notifications.push((
+                    EventTrigger::StrategyChanged,
+                    strategy_variables(&strategy, "deleted"),
+                ));
+            }
+            SettingsChange::InsertEvent {
+                event_trigger,
+                data,
+            } => {
+                insert_event(&mut space, event_trigger, data)?;
+            }
+        }
+    }
+
+    store_space(&space);
+    for strategy_id in changed_strategies {
+        invalidate_voting_power_cache(space_id, strategy_id);
+    }
+    for (event_trigger, event_data) in notifications {
+        notify(space_id, event_trigger, event_data);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,160 @@
+use ic_cdk::{query, update};
+
+use super::roles;
+use crate::{
+    get_space,
+    types::{
+        event::EventTrigger,
+        role::SpaceRole,
+        subscription::{Subscription, SubscriptionData, SubscriptionKey, UnfollowData},
+    },
+    utils::{normalize_address, verify_signature},
+    SUBSCRIPTIONS,
+};
+
+const MAX_WEBHOOK_SUBSCRIBERS: usize = 50;
+
+/// Follows a space, or updates the notifications of a followed space. With a
+/// webhook, the follower is notified of each of `triggers`; registering one
+/// requires the Member role.
+#[update]
+fn follow_space(data: SubscriptionData) -> Result<Subscription, String> {
+    let message = data.message;
+    let signer = verify_signature(&message, &data.signature, &message.address)?;
+    let signer = normalize_address(&signer);
+    let space = get_space(message.space_id).ok_or("Invalid space id")?;
+
+    // Every webhook costs an outcall per event, so only members may register
+    // one and each space has a limited number of them.
+    if let Some(ref webhook) = message.webhook {
+        webhook.check()?;
+        roles::require_role(&space, &signer, &SpaceRole::Member)?;
+
+        let webhooks = space_subscriptions(space.id)
+            .iter()
+            .filter(|s| s.webhook.is_some() && s.address != signer)
+            .count();
+        if webhooks >= MAX_WEBHOOK_SUBSCRIBERS {
+            return Err("Space has reached its number of webhook subscribers".to_owned());
+        }
+    }
+
+    roles::consume_timestamp(message.space_id, &signer, message.timestamp)?;
+
+    let mut triggers: Vec<EventTrigger> = Vec::new();
+    for trigger in message.triggers {
+        if !triggers.contains(&trigger) {
+            triggers.push(trigger);
+        }
+    }
+
+    let subscription = Subscription {
+        space_id: message.space_id,
+        address: signer.clone(),
+        triggers,
+        webhook: message.webhook,
+        timestamp: message.timestamp,
+    };
+
+    let key = SubscriptionKey {
+        space_id: message.space_id,
+        address: signer,
+    };
+    SUBSCRIPTIONS.with(|s| s.borrow_mut().insert(key, subscription.clone()));
+
+    Ok(subscription.redacted())
+}
+
+#[update]
+fn unfollow_space(data: UnfollowData) -> Result<Subscription, String> {
+    let message = &data.message;
+    let signer = verify_signature(message, &data.signature, &message.address)?;
+    let signer = normalize_address(&signer);
+
+    let key = SubscriptionKey {
+        space_id: message.space_id,
+        address: signer.clone(),
+    };
+    if !SUBSCRIPTIONS.with(|s| s.borrow().contains_key(&key)) {
+        return Err("Address doesn't follow the space".to_owned());
+    }
+
+    roles::consume_timestamp(message.space_id, &signer, message.timestamp)?;
+
+    SUBSCRIPTIONS
+        .with(|s| s.borrow_mut().remove(&key))
+        .map(Subscription::redacted)
+        .ok_or("Address doesn't follow the space".to_owned())
+}
+
+#[query]
+fn get_subscription(space_id: u32, address: String) -> Option<Subscription> {
+    let key = SubscriptionKey {
+        space_id,
+        address: address.to_lowercase(),
+    };
+
+    SUBSCRIPTIONS
+        .with(|s| s.borrow().get(&key))
+        .map(Subscription::redacted)
+}
+
+#[query]
+fn get_space_followers(space_id: u32) -> Vec<String> {
+    space_subscriptions(space_id)
+        .into_iter()
+        .map(|s| s.address)
+        .collect()
+}
+
+#[query]
+fn get_followed_spaces(address: String) -> Vec<u32> {
+    SUBSCRIPTIONS.with(|s| {
+        s.borrow()
+            .iter()
+            .filter(|(key, _)| key.address.eq_ignore_ascii_case(&address))
+            .map(|(key, _)| key.space_id)
+            .collect()
+    })
+}
+
+/// Subscriptions of a space that want to be notified of `trigger`.
+pub fn subscribers(space_id: u32, trigger: &EventTrigger) -> Vec<Subscription> {
+    space_subscriptions(space_id)
+        .into_iter()
+        .filter(|s| s.webhook.is_some() && s.triggers.contains(trigger))
+        .collect()
+}
+
+/// Drops the followers of a deleted space.
+pub fn remove_space_subscriptions(space_id: u32) {
+    let keys: Vec<SubscriptionKey> = space_subscriptions(space_id)
+        .into_iter()
+        .map(|s| SubscriptionKey {
+            space_id,
+            address: s.address,
+        })
+        .collect();
+
+    SUBSCRIPTIONS.with(|s| {
+        let mut subscriptions = s.borrow_mut();
+        for key in keys {
+            subscriptions.remove(&key);
+        }
+    });
+}
+
+fn space_subscriptions(space_id: u32) -> Vec<Subscription> {
+    let start = SubscriptionKey {
+        space_id,
+        address: String::new(),
+    };
+
+    SUBSCRIPTIONS.with(|s| {
+        s.borrow()
+            .range(start..)
+            .take_while(|(key, _)| key.space_id == space_id)
+            .map(|(_, subscription)| subscription)
+            .collect()
+    })
+}
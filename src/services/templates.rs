@@ -0,0 +1,169 @@
+use ic_cdk::{query, update};
+
+use super::roles;
+use crate::{
+    get_space,
+    types::{
+        proposal_template::{InsertProposalTemplate, ProposalTemplate},
+        role::{Authorization, SpaceRole},
+        space::Space,
+    },
+    update_space_templates,
+};
+
+/// Adds a proposal template to a space, or replaces an existing one. Only
+/// Admins may manage templates.
+#[update]
+fn save_proposal_template(
+    space_id: u32,
+    template_id: Option<u32>,
+    template: InsertProposalTemplate,
+    auth: Authorization,
+) -> Result<ProposalTemplate, String> {
+    let space = get_space(space_id).ok_or("Invalid space id")?;
+    roles::authorize_role(
+        &space,
+        "save_proposal_template",
+        &(template_id, &template),
+        &auth,
+        SpaceRole::Admin,
+    )?;
+
+    if template.name.trim().is_empty() {
+        return Err("Template name is empty".to_owned());
+    }
+
+    if template
+        .required_sections
+        .iter()
+        .any(|s| s.trim().is_empty())
+    {
+        return Err("Required sections must have a name".to_owned());
+    }
+
+    if let Some(duration) = template.vote_duration {
+        if duration < space.min_vote_duration
+            || (space.max_vote_duration > 0 && duration > space.max_vote_duration)
+        {
+            return Err("Template vote duration is outside the space bounds".to_owned());
+        }
+    }
+
+    let mut templates = space.templates;
+
+    if templates
+        .iter()
+        .any(|t| Some(t.id) != template_id && t.name.eq_ignore_ascii_case(&template.name))
+    {
+        return Err("A template with this name already exists".to_owned());
+    }
+
+    let id = match template_id {
+        Some(id) => {
+            let index = templates
+                .iter()
+                .position(|t| t.id == id)
+                .ok_or("Invalid template id")?;
+
+            templates.remove(index);
+            id
+        }
+        None => templates.iter().map(|t| t.id).max().unwrap_or(0) + 1,
+    };
+
+    let new_template = ProposalTemplate {
+        id,
+        name: template.name,
+        mechanism: template.mechanism,
+        options: template.options,
+        vote_delay: template.vote_delay,
+        vote_duration: template.vote_duration,
+        body: template.body,
+        required_sections: template.required_sections,
+        updated_at: auth.timestamp,
+    };
+
+    templates.push(new_template.clone());
+    templates.sort_by_key(|t| t.id);
+    update_space_templates(space.id, templates);
+
+    Ok(new_template)
+}
+
+#[update]
+fn delete_proposal_template(
+    space_id: u32,
+    template_id: u32,
+    auth: Authorization,
+) -> Result<ProposalTemplate, String> {
+    let space = get_space(space_id).ok_or("Invalid space id")?;
+    roles::authorize_role(
+        &space,
+        "delete_proposal_template",
+        &(template_id,),
+        &auth,
+        SpaceRole::Admin,
+    )?;
+
+    let mut templates = space.templates;
+    let index = templates
+        .iter()
+        .position(|t| t.id == template_id)
+        .ok_or("Invalid template id")?;
+
+    let template = templates.remove(index);
+    update_space_templates(space.id, templates);
+
+    Ok(template)
+}
+
+#[query]
+fn get_proposal_templates(space_id: u32) -> Option<Vec<ProposalTemplate>> {
+    Some(get_space(space_id)?.templates)
+}
+
+#[query]
+fn get_proposal_template(space_id: u32, template_id: u32) -> Option<ProposalTemplate> {
+    get_space(space_id)?
+        .templates
+        .into_iter()
+        .find(|t| t.id == template_id)
+}
+
+pub fn find_template(space: &Space, template_id: u32) -> Result<&ProposalTemplate, String> {
+    space
+        .templates
+        .iter()
+        .find(|t| t.id == template_id)
+        .ok_or("Invalid template id".to_owned())
+}
+
+/// Checks a proposal against its template: the mechanism must match and the
+/// description needs a markdown heading for every required section.
+pub fn check_proposal(
+    template: &ProposalTemplate,
+    mechanism: u32,
+    description: &str,
+) -> Result<(), String> {
+    if mechanism != template.mechanism {
+        return Err("Proposal mechanism does not match its template".to_owned());
+    }
+
+    let headings: Vec<&str> = description
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with('#'))
+        .map(|line| line.trim_start_matches('#').trim())
+        .collect();
+
+    for section in &template.required_sections {
+        if !headings
+            .iter()
+            .any(|h| h.eq_ignore_ascii_case(section.trim()))
+        {
+            return Err(format!("Proposal is missing the \"{}\" section", section));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::ops::Bound;
+
+use ic_cdk::{query, update};
+
+use crate::{
+    caller_is_controller, get_space, get_spaces,
+    types::{
+        proposal::Proposal,
+        proposal_option_vote::{
+            AddressVote, AddressVoteCursor, AddressVoteKey, AddressVotePage, ProposalOptionVote,
+        },
+        space::Space,
+    },
+    MAX_PAGE_SIZE, VOTES_BY_ADDRESS,
+};
+
+// Index of votes by voter address, kept next to the votes stored in the
+// spaces so the history of an address doesn't need a scan of every space.
+
+/// Returns up to `limit` votes cast by `address` in any space, starting after
+/// `cursor`.
+#[query]
+fn get_votes_by_address(
+    address: String,
+    cursor: Option<AddressVoteCursor>,
+    limit: u32,
+) -> AddressVotePage {
+    let address = address.to_lowercase();
+    let limit = limit.clamp(1, MAX_PAGE_SIZE) as usize;
+
+    let start = match cursor {
+        Some(cursor) => Bound::Excluded(AddressVoteKey {
+            address: address.clone(),
+            space_id: cursor.space_id,
+            proposal_id: cursor.proposal_id,
+            option_id: cursor.option_id,
+            vote_id: cursor.vote_id,
+        }),
+        None => Bound::Included(AddressVoteKey {
+            address: address.clone(),
+            space_id: 0,
+            proposal_id: 0,
+            option_id: 0,
+            vote_id: 0,
+        }),
+    };
+
+    let mut keys: Vec<AddressVoteKey> = VOTES_BY_ADDRESS.with(|v| {
+        v.borrow()
+            .range((start, Bound::Unbounded))
+            .take_while(|(key, _)| key.address == address)
+            .take(limit + 1)
+            .map(|(key, _)| key)
+            .collect()
+    });
+
+    let next_cursor = if keys.len() > limit {
+        keys.truncate(limit);
+        keys.last().map(|key| AddressVoteCursor {
+            space_id: key.space_id,
+            proposal_id: key.proposal_id,
+            option_id: key.option_id,
+            vote_id: key.vote_id,
+        })
+    } else {
+        None
+    };
+
+    let mut spaces: HashMap<u32, Option<Space>> = HashMap::new();
+    let votes = keys
+        .iter()
+        .filter_map(|key| {
+            let space = spaces
+                .entry(key.space_id)
+                .or_insert_with(|| get_space(key.space_id))
+                .as_ref()?;
+            address_vote(space, key)
+        })
+        .collect();
+
+    AddressVotePage { votes, next_cursor }
+}
+
+/// Indexes the votes already stored in the spaces, for votes cast before the
+/// index existed.
+#[update(guard = "caller_is_controller")]
+fn rebuild_vote_index() -> u64 {
+    let mut count = 0;
+    for space in get_spaces().unwrap_or_default() {
+        for proposal in &space.proposals {
+            for option in &proposal.options {
+                for vote in &option.votes {
+                    index_vote(space.id, proposal.id, vote);
+                    count += 1;
+                }
+            }
+        }
+    }
+
+    count
+}
+
+pub fn index_vote(space_id: u32, proposal_id: u32, vote: &ProposalOptionVote) {
+    let key = vote_key(space_id, proposal_id, vote);
+    VOTES_BY_ADDRESS.with(|v| v.borrow_mut().insert(key, ()));
+}
+
+pub fn unindex_vote(space_id: u32, proposal_id: u32, vote: &ProposalOptionVote) {
+    let key = vote_key(space_id, proposal_id, vote);
+    VOTES_BY_ADDRESS.with(|v| v.borrow_mut().remove(&key));
+}
+
+/// Drops the votes of a deleted proposal from the index.
+pub fn remove_proposal_votes(proposal: &Proposal) {
+    for option in &proposal.options {
+        for vote in &option.votes {
+            unindex_vote(proposal.space_id, proposal.id, vote);
+        }
+    }
+}
+
+fn vote_key(space_id: u32, proposal_id: u32, vote: &ProposalOptionVote) -> AddressVoteKey {
+    AddressVoteKey {
+        address: vote.user_address.to_lowercase(),
+        space_id,
+        proposal_id,
+        option_id: vote.option_id,
+        vote_id: vote.id,
+    }
+}
+
+fn address_vote(space: &Space, key: &AddressVoteKey) -> Option<AddressVote> {
+    let proposal = space.proposals.iter().find(|p| p.id == key.proposal_id)?;
+    let option = proposal.options.iter().find(|o| o.id == key.option_id)?;
+    let vote = option.votes.iter().find(|v| v.id == key.vote_id)?;
+
+    Some(AddressVote {
+        space_id: key.space_id,
+        proposal_id: key.proposal_id,
+        proposal_title: proposal.title.clone(),
+        option_name: option.name.clone(),
+        vote: vote.clone(),
+    })
+}
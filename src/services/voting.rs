@@ -1,8 +1,9 @@
 use core::panic;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::str::FromStr;
 
 use candid::Nat;
-use ethers_core::types::{Address, Signature};
+use ethers_core::types::{Address, U256};
 use ic_cdk::{
     api::management_canister::http_request::{
         http_request, CanisterHttpRequestArgument, HttpHeader, HttpMethod,
@@ -10,74 +11,146 @@ use ic_cdk::{
     update,
 };
 
-use crate::services::events::trigger_events;
+use crate::services::events::{notify, proposal_variables};
 
 use crate::{
     get_events_by_space, get_proposal, get_space, get_strategies, get_votes, insert_vote,
     types::{
+        delegation::DelegatedPower,
         event::{Event, EventData, EventTrigger},
-        space,
+        evm_strategy::EvmStrategy,
+        proposal::{Proposal, ProposalStatus, SnapshotBlock},
+        proposal_option_vote::ProposalOptionVote,
+        space::{self, Space},
         strategy::{Strategy, StrategyData},
-        vote::VoteData,
+        vote::{VoteData, VoteMessage},
+        voting_power_cache::{CachedVotingPower, VotingPowerCacheKey},
         webhook_event::WebhookEvent,
     },
+    update_proposal_options,
+    utils::{normalize_address, verify_signature},
+    VOTING_POWER_CACHE,
 };
 
-use super::eth_rpc::eth_call;
+use super::delegation::get_space_delegations;
+use super::eth_rpc::{block_number, eth_call};
+use super::lifecycle::{now, refresh_status, total_voting_power};
+use super::multicall::{aggregate3, Call};
+use super::roles::require_role;
+use super::vote_history;
 
-#[update]
-async fn vote(data: VoteData) -> Result<Nat, String> {
-    let message_json = serde_json::to_string(&data.message).unwrap();
-    let signature = data.signature.parse::<Signature>().unwrap();
+const MAX_REASON_LENGTH: usize = 2000;
 
-    let recovered_address = signature.recover(message_json).unwrap();
-    let parsed_address = data.message.address.parse::<Address>().unwrap();
+/// Block voting power is read at.
+pub enum BlockHeight {
+    Latest,
+    /// A block number or tag, used on every chain.
+    At(String),
+    /// The block pinned for each chain when the proposal was created.
+    Snapshot(Vec<SnapshotBlock>),
+}
 
-    if recovered_address != parsed_address {
-        return Err("Invalid signature".to_owned());
+impl BlockHeight {
+    fn for_chain(&self, chain_id: u64) -> Option<String> {
+        match self {
+            BlockHeight::Latest => None,
+            BlockHeight::At(block_height) => Some(block_height.clone()),
+            BlockHeight::Snapshot(blocks) => blocks
+                .iter()
+                .find(|b| b.chain_id == chain_id)
+                .map(|b| format!("0x{:x}", b.block)),
+        }
     }
+}
+
+#[update]
+async fn vote(data: VoteData) -> Result<Nat, String> {
+    let recovered_address =
+        verify_signature(&data.message, &data.signature, &data.message.address)?;
 
     if !data.message.address.starts_with("0x") {
         return Err("Only Ethereum address is supported for now".into());
     }
 
     let space = get_space(data.message.space_id);
-    let proposal = get_proposal(data.message.space_id, data.message.proposal_id);
+    let proposal = refresh_status(data.message.space_id, data.message.proposal_id).ok();
 
     if space.is_none() || proposal.is_none() {
         return Err("Invalid vote message".to_owned());
     }
 
     let (space, proposal) = (space.unwrap(), proposal.unwrap());
-    let vote_timestamp = ic_cdk::api::time() / 1_000_000_000;
-
-    // date_created = 10s
-    // vote_delay = 3s
-    // vote_duration = 60s
-    // 0s -------------- 10s -------------- 13s ------------- 73s ------------- inf s
-    //           proposal created       voting available    voting finished
-    if (proposal.date_created + space.vote_delay as u64) > vote_timestamp
-        || (proposal.date_created + space.vote_duration as u64 + space.vote_delay as u64)
-            < vote_timestamp
+    let vote_timestamp = now();
+
+    if proposal.status != ProposalStatus::Active {
+        return Err("Voting is not available for this proposal".to_owned());
+    }
+
+    if let Some(ref role) = space.min_vote_role {
+        require_role(&space, &normalize_address(&recovered_address), role)?;
+    }
+
+    if !proposal
+        .options
+        .iter()
+        .any(|o| o.id == data.message.option_id)
     {
+        return Err("Invalid vote message".to_owned());
+    }
+
+    if let Some(ref reason) = data.message.reason {
+        if reason.len() > MAX_REASON_LENGTH {
+            return Err("Vote reason is too long".to_owned());
+        }
+    }
+
+    check_vote_change(&space, &proposal, &data.message)?;
+
+    let block_height = proposal
+        .snapshot_blocks
+        .clone()
+        .map_or(BlockHeight::Latest, BlockHeight::Snapshot);
+    let own_voting_power =
+        get_strategies_voting_power(&recovered_address, data.message.space_id, &block_height)
+            .await?;
+    let delegated_power =
+        get_delegated_voting_power(&recovered_address, data.message.space_id, &block_height)
+            .await?;
+
+    // Outcalls above may interleave with other votes, so re-read the proposal
+    // before deciding which delegators still count towards this vote.
+    let proposal = get_proposal(data.message.space_id, data.message.proposal_id)
+        .ok_or("Invalid vote message")?;
+
+    if proposal.status != ProposalStatus::Active {
         return Err("Voting is not available for this proposal".to_owned());
     }
 
-    let voting_power = get_voting_power(&recovered_address, data.message.space_id, None)
-        .await
-        .unwrap();
+    check_vote_change(&space, &proposal, &data.message)?;
+
+    // A delegator who voted themselves overrides their delegation, and power
+    // already counted in another vote, e.g. before a re-delegation, isn't
+    // counted twice.
+    let delegated_power: Vec<DelegatedPower> = delegated_power
+        .into_iter()
+        .filter(|d| !has_voted(&proposal, &d.delegator))
+        .filter(|d| !is_delegated_elsewhere(&proposal, &d.delegator, &data.message.address))
+        .collect();
+
+    let mut voting_power = own_voting_power;
+    for delegated in delegated_power.iter() {
+        voting_power += delegated.voting_power.clone();
+    }
 
     if voting_power < space.min_vote_power {
         return Err("Insufficient voting power".to_owned());
     }
 
-    if proposal.options.iter().any(|opt| {
-        opt.votes
-            .iter()
-            .any(|vote| vote.user_address == data.message.address)
-    }) {
-        return Err("User has already voted".to_owned());
-    }
+    remove_vote(
+        data.message.space_id,
+        data.message.proposal_id,
+        &data.message.address,
+    );
 
     insert_vote(
         data.message.space_id,
@@ -88,17 +161,48 @@ async fn vote(data: VoteData) -> Result<Nat, String> {
         vote_timestamp,
         data.signature,
         voting_power.clone(),
-    );
+        delegated_power,
+        data.message.reason.clone(),
+        data.message.timestamp,
+    )
+    .ok_or("Invalid vote message")?;
 
-    trigger_events(
+    remove_delegated_power(
         data.message.space_id,
-        EventTrigger::Vote,
-        HashMap::from([
-            ("power", voting_power.to_string()),
-            ("address", data.message.address.clone()),
-        ]),
-    )
-    .await;
+        data.message.proposal_id,
+        &data.message.address,
+    );
+
+    let turnout_before = total_voting_power(&proposal);
+    let proposal = get_proposal(data.message.space_id, data.message.proposal_id)
+        .ok_or("Invalid vote message")?;
+    let option_name = proposal
+        .options
+        .iter()
+        .find(|o| o.id == data.message.option_id)
+        .map_or(String::new(), |o| o.name.clone());
+
+    let mut event_data = proposal_variables(&proposal);
+    event_data.extend([
+        ("power", voting_power.to_string()),
+        ("address", data.message.address.clone()),
+        ("option_id", data.message.option_id.to_string()),
+        ("option_name", option_name),
+    ]);
+
+    if space.quorum > Nat::from(0 as u32)
+        && turnout_before < space.quorum
+        && total_voting_power(&proposal) >= space.quorum
+    {
+        notify(
+            data.message.space_id,
+            EventTrigger::QuorumReached,
+            event_data.clone(),
+        );
+    }
+
+    // Webhooks are delivered in the background so the vote doesn't wait on them.
+    notify(data.message.space_id, EventTrigger::Vote, event_data);
 
     Ok(voting_power)
 }
@@ -109,60 +213,388 @@ async fn voting_power(
     space_id: u32,
     block_height: Option<String>,
 ) -> Result<Nat, String> {
-    let voting_power = get_voting_power(&address.parse().unwrap(), space_id, block_height)
+    let block_height = block_height.map_or(BlockHeight::Latest, BlockHeight::At);
+    let voting_power = get_voting_power(&address.parse().unwrap(), space_id, &block_height)
         .await
         .unwrap();
 
     Ok(voting_power)
 }
 
-async fn get_voting_power(
+fn find_vote<'a>(proposal: &'a Proposal, address: &str) -> Option<&'a ProposalOptionVote> {
+    proposal
+        .options
+        .iter()
+        .flat_map(|opt| opt.votes.iter())
+        .find(|vote| vote.user_address.eq_ignore_ascii_case(address))
+}
+
+fn has_voted(proposal: &Proposal, address: &str) -> bool {
+    find_vote(proposal, address).is_some()
+}
+
+/// Whether the power of `delegator` counts towards a vote of someone other
+/// than `voter` in the proposal.
+fn is_delegated_elsewhere(proposal: &Proposal, delegator: &str, voter: &str) -> bool {
+    proposal
+        .options
+        .iter()
+        .flat_map(|opt| opt.votes.iter())
+        .filter(|vote| !vote.user_address.eq_ignore_ascii_case(voter))
+        .any(|vote| {
+            vote.delegated_power
+                .iter()
+                .any(|d| d.delegator.eq_ignore_ascii_case(delegator))
+        })
+}
+
+/// A voter may only replace their vote in spaces that allow it, and only with
+/// a message signed later than the one it replaces, so that an older signed
+/// vote can't be replayed over a newer one.
+fn check_vote_change(
+    space: &Space,
+    proposal: &Proposal,
+    message: &VoteMessage,
+) -> Result<(), String> {
+    let previous = match find_vote(proposal, &message.address) {
+        Some(previous) => previous,
+        None => return Ok(()),
+    };
+
+    if !space.allow_vote_change {
+        return Err("User has already voted".to_owned());
+    }
+
+    match message.timestamp {
+        Some(timestamp) if timestamp > previous.message_timestamp.unwrap_or(0) => Ok(()),
+        _ => Err("Vote message is outdated".to_owned()),
+    }
+}
+
+fn remove_vote(space_id: u32, proposal_id: u32, address: &str) {
+    let proposal = match get_proposal(space_id, proposal_id) {
+        Some(proposal) => proposal,
+        None => return,
+    };
+
+    if !has_voted(&proposal, address) {
+        return;
+    }
+
+    let mut options = proposal.options;
+    for option in options.iter_mut() {
+        for vote in option.votes.iter() {
+            if vote.user_address.eq_ignore_ascii_case(address) {
+                vote_history::unindex_vote(space_id, proposal_id, vote);
+            }
+        }
+        option
+            .votes
+            .retain(|vote| !vote.user_address.eq_ignore_ascii_case(address));
+    }
+
+    update_proposal_options(space_id, proposal_id, options);
+}
+
+/// Takes the power of `delegator` out of any vote in the proposal that
+/// counted it through a delegation.
+fn remove_delegated_power(space_id: u32, proposal_id: u32, delegator: &str) {
+    let proposal = match get_proposal(space_id, proposal_id) {
+        Some(proposal) => proposal,
+        None => return,
+    };
+
+    let mut options = proposal.options;
+    let mut changed = false;
+
+    for option in options.iter_mut() {
+        for vote in option.votes.iter_mut() {
+            let index = vote
+                .delegated_power
+                .iter()
+                .position(|d| d.delegator.eq_ignore_ascii_case(delegator));
+
+            if let Some(index) = index {
+                let removed = vote.delegated_power.remove(index);
+                vote.voting_power -= removed.voting_power;
+                changed = true;
+            }
+        }
+    }
+
+    if changed {
+        update_proposal_options(space_id, proposal_id, options);
+    }
+}
+
+/// Own voting power of `address` plus everything delegated to it in the space.
+pub async fn get_voting_power(
     address: &Address,
     space_id: u32,
-    block_height: Option<String>,
+    block_height: &BlockHeight,
+) -> Result<Nat, String> {
+    let mut total_voting_power =
+        get_strategies_voting_power(address, space_id, block_height).await?;
+
+    for delegated in get_delegated_voting_power(address, space_id, block_height).await? {
+        total_voting_power += delegated.voting_power;
+    }
+
+    Ok(total_voting_power)
+}
+
+async fn get_delegated_voting_power(
+    delegate: &Address,
+    space_id: u32,
+    block_height: &BlockHeight,
+) -> Result<Vec<DelegatedPower>, String> {
+    let delegate = normalize_address(delegate);
+    let mut delegators = Vec::new();
+    let mut addresses = Vec::new();
+
+    for delegation in get_space_delegations(space_id) {
+        if delegation.delegate.as_ref() != Some(&delegate) {
+            continue;
+        }
+
+        let address = delegation
+            .delegator
+            .parse::<Address>()
+            .map_err(|_| "Invalid delegator address")?;
+        delegators.push(delegation.delegator);
+        addresses.push(address);
+    }
+
+    let voting_powers = get_strategies_voting_powers(&addresses, space_id, block_height).await?;
+
+    Ok(delegators
+        .into_iter()
+        .zip(voting_powers)
+        .filter(|(_, voting_power)| *voting_power > Nat::from(0 as u32))
+        .map(|(delegator, voting_power)| DelegatedPower {
+            delegator,
+            voting_power,
+        })
+        .collect())
+}
+
+async fn get_strategies_voting_power(
+    address: &Address,
+    space_id: u32,
+    block_height: &BlockHeight,
 ) -> Result<Nat, String> {
+    let voting_powers =
+        get_strategies_voting_powers(std::slice::from_ref(address), space_id, block_height).await?;
+
+    Ok(voting_powers
+        .into_iter()
+        .next()
+        .unwrap_or(Nat::from(0 as u32)))
+}
+
+/// Strategy voting power of each of `addresses`, in the same order.
+async fn get_strategies_voting_powers(
+    addresses: &[Address],
+    space_id: u32,
+    block_height: &BlockHeight,
+) -> Result<Vec<Nat>, String> {
     let strategies: Vec<Strategy> = get_strategies(space_id)
         .unwrap()
         .into_iter()
         .filter(|s| s.space_id == space_id)
         .collect();
 
-    let mut total_voting_power = Nat::from(0 as u32);
+    let mut voting_powers = vec![Nat::from(0 as u32); addresses.len()];
+
+    // The strategies of every address on the same chain are evaluated in a
+    // single Multicall3 call.
+    let mut calls_by_chain: BTreeMap<u64, Vec<((usize, u32), Call)>> = BTreeMap::new();
+
+    for (index, address) in addresses.iter().enumerate() {
+        for strategy in strategies.iter() {
+            let evm_strategy = match strategy.data {
+                StrategyData::Evm(ref evm_strategy) => evm_strategy,
+                _ => return Err("Only EVM strategies are supported for now".into()),
+            };
+
+            let pinned_block = block_height
+                .for_chain(evm_strategy.chain_id)
+                .as_deref()
+                .and_then(parse_block_number);
+
+            if let Some(block) = pinned_block {
+                if let Some(cached) = get_cached_voting_power(space_id, strategy.id, address, block)
+                {
+                    voting_powers[index] += cached;
+                    continue;
+                }
+            }
 
-    for strategy in strategies {
-        let voting_power = call_strategy(&address, &strategy, block_height.clone()).await?;
-        total_voting_power += voting_power;
+            calls_by_chain
+                .entry(evm_strategy.chain_id)
+                .or_default()
+                .push(((index, strategy.id), strategy_call(address, evm_strategy)));
+        }
     }
 
-    return Ok(total_voting_power);
+    for (chain_id, strategy_calls) in calls_by_chain {
+        let (targets, calls): (Vec<(usize, u32)>, Vec<Call>) = strategy_calls.into_iter().unzip();
+        let chain_block_height = block_height.for_chain(chain_id);
+        let pinned_block = chain_block_height.as_deref().and_then(parse_block_number);
+
+        let responses = if calls.len() == 1 {
+            let call = &calls[0];
+            vec![
+                eth_call(
+                    chain_id,
+                    call.target.clone(),
+                    call.data.clone(),
+                    chain_block_height.clone(),
+                    2048,
+                )
+                .await,
+            ]
+        } else {
+            aggregate3(chain_id, &calls, chain_block_height.clone()).await?
+        };
+
+        for ((index, strategy_id), response) in targets.into_iter().zip(responses) {
+            // A reverted strategy fails the lookup instead of counting as no power.
+            let voting_power = parse_voting_power(response)?;
+
+            if let Some(block) = pinned_block {
+                let address = &addresses[index];
+                cache_voting_power(space_id, strategy_id, address, block, voting_power.clone());
+            }
+
+            voting_powers[index] += voting_power;
+        }
+    }
+
+    Ok(voting_powers)
+}
+
+/// Latest block of every chain the space's strategies read from.
+pub async fn snapshot_blocks(space_id: u32) -> Result<Vec<SnapshotBlock>, String> {
+    let mut chain_ids: Vec<u64> = get_strategies(space_id)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|s| match s.data {
+            StrategyData::Evm(evm_strategy) => Some(evm_strategy.chain_id),
+            _ => None,
+        })
+        .collect();
+    chain_ids.sort_unstable();
+    chain_ids.dedup();
+
+    let mut blocks = Vec::new();
+    for chain_id in chain_ids {
+        blocks.push(SnapshotBlock {
+            chain_id,
+            block: block_number(chain_id).await?,
+        });
+    }
+
+    Ok(blocks)
 }
 
-async fn call_strategy(
+/// Parses an explicit block number given either as hex (`0x...`) or decimal.
+/// Tags such as `latest` move over time, so their results are never cached.
+fn parse_block_number(block_height: &str) -> Option<u64> {
+    match block_height.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => block_height.parse::<u64>().ok(),
+    }
+}
+
+fn voting_power_cache_key(
+    space_id: u32,
+    strategy_id: u32,
     address: &Address,
-    strategy: &Strategy,
-    block_height: Option<String>,
-) -> Result<Nat, String> {
-    if let StrategyData::Evm(ref evm_strategy) = strategy.data {
-        let str_address = format!("{:x}", &address).replace("0x", "");
-        let data = evm_strategy
-            .bytecode
-            .clone()
-            .replace("$voterAddress", &str_address);
-
-        let response = eth_call(evm_strategy.contract_address.clone(), data, block_height).await;
-
-        if let Ok(value) = response {
-            if value == "0x" {
-                return Ok(Nat::from(0 as u8));
-            }
+    block: u64,
+) -> VotingPowerCacheKey {
+    VotingPowerCacheKey {
+        space_id,
+        strategy_id,
+        address: format!("{:x}", address),
+        block,
+    }
+}
 
-            Ok(Nat::from(
-                u128::from_str_radix(&value.trim_start_matches("0x"), 16).unwrap(),
-            ))
-        } else {
-            Err("Unable to parse response from contract call".into())
+fn get_cached_voting_power(
+    space_id: u32,
+    strategy_id: u32,
+    address: &Address,
+    block: u64,
+) -> Option<Nat> {
+    let key = voting_power_cache_key(space_id, strategy_id, address, block);
+
+    VOTING_POWER_CACHE.with(|cache| cache.borrow().get(&key).map(|v| v.voting_power))
+}
+
+fn cache_voting_power(
+    space_id: u32,
+    strategy_id: u32,
+    address: &Address,
+    block: u64,
+    voting_power: Nat,
+) {
+    let key = voting_power_cache_key(space_id, strategy_id, address, block);
+
+    VOTING_POWER_CACHE.with(|cache| {
+        cache
+            .borrow_mut()
+            .insert(key, CachedVotingPower { voting_power })
+    });
+}
+
+/// Drops every cached result of a strategy, e.g. after its contract or
+/// bytecode changed.
+pub fn invalidate_voting_power_cache(space_id: u32, strategy_id: u32) {
+    let start = VotingPowerCacheKey {
+        space_id,
+        strategy_id,
+        address: String::new(),
+        block: 0,
+    };
+
+    VOTING_POWER_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        let keys: Vec<VotingPowerCacheKey> = cache
+            .range(start..)
+            .map(|(key, _)| key)
+            .take_while(|key| key.space_id == space_id && key.strategy_id == strategy_id)
+            .collect();
+
+        for key in keys {
+            cache.remove(&key);
         }
-    } else {
-        Err("Only EVM strategies are supported for now".into())
+    });
+}
+
+fn strategy_call(address: &Address, evm_strategy: &EvmStrategy) -> Call {
+    let str_address = format!("{:x}", &address).replace("0x", "");
+    let data = evm_strategy
+        .bytecode
+        .clone()
+        .replace("$voterAddress", &str_address);
+
+    Call {
+        target: evm_strategy.contract_address.clone(),
+        data,
     }
 }
+
+fn parse_voting_power(response: Result<String, String>) -> Result<Nat, String> {
+    let value = response.map_err(|_| "Unable to parse response from contract call".to_owned())?;
+    let value = value.trim_start_matches("0x");
+
+    if value.is_empty() {
+        return Ok(Nat::from(0 as u8));
+    }
+
+    U256::from_str_radix(value, 16)
+        .ok()
+        .and_then(|power| Nat::from_str(&power.to_string()).ok())
+        .ok_or_else(|| "Unable to parse response from contract call".into())
+}
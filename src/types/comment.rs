@@ -0,0 +1,106 @@
+use candid::{CandidType, Decode, Deserialize, Encode};
+use ic_stable_structures::{storable::Bound, Storable};
+use serde::Serialize;
+use std::borrow::Cow;
+
+const MAX_KEY_SIZE: u32 = 100;
+
+/// Comments of one proposal are adjacent and ordered by id, so a page is a
+/// range scan.
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CommentKey {
+    pub space_id: u32,
+    pub proposal_id: u32,
+    pub comment_id: u32,
+}
+
+impl Storable for CommentKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: MAX_KEY_SIZE,
+        is_fixed_size: false,
+    };
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct Comment {
+    pub id: u32,
+    pub space_id: u32,
+    pub proposal_id: u32,
+    // Comment this one replies to, `None` for top level comments
+    pub parent_id: Option<u32>,
+    pub author: String,
+    pub body: String,
+    pub timestamp: u64,
+    pub signature: String,
+    // Set by Moderators; queries never return the body of hidden comments
+    pub hidden: bool,
+}
+
+impl Comment {
+    /// The comment without its body once hidden, for query results.
+    pub fn redacted(mut self) -> Self {
+        if self.hidden {
+            self.body.clear();
+        }
+        self
+    }
+}
+
+impl Storable for Comment {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, Default, Serialize)]
+pub struct CommentMessage {
+    pub space_id: u32,
+    pub proposal_id: u32,
+    pub parent_id: Option<u32>,
+    pub author: String,
+    pub body: String,
+    pub timestamp: u64,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, Default)]
+pub struct CommentData {
+    pub signature: String,
+    pub message: CommentMessage,
+}
+
+/// Signed by a Moderator to hide a comment, or to show it again.
+#[derive(CandidType, Deserialize, Debug, Clone, Default, Serialize)]
+pub struct CommentModerationMessage {
+    pub space_id: u32,
+    pub proposal_id: u32,
+    pub comment_id: u32,
+    pub address: String,
+    pub hidden: bool,
+    pub timestamp: u64,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, Default)]
+pub struct CommentModerationData {
+    pub signature: String,
+    pub message: CommentModerationMessage,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct CommentPage {
+    pub comments: Vec<Comment>,
+    pub next_cursor: Option<u32>,
+}
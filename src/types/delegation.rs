@@ -0,0 +1,74 @@
+use candid::{CandidType, Decode, Deserialize, Encode, Nat};
+use ic_stable_structures::{storable::Bound, Storable};
+use serde::Serialize;
+use std::borrow::Cow;
+
+const MAX_KEY_SIZE: u32 = 200;
+
+/// A delegation is either scoped to one space or global (`space_id: None`).
+/// A space scoped delegation takes precedence over a global one.
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DelegationKey {
+    pub delegator: String,
+    pub space_id: Option<u32>,
+}
+
+impl Storable for DelegationKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: MAX_KEY_SIZE,
+        is_fixed_size: false,
+    };
+}
+
+/// A revoked delegation keeps its entry with `delegate: None`, so that the
+/// timestamp of the last signed message keeps rejecting replays.
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct Delegation {
+    pub delegator: String,
+    pub delegate: Option<String>,
+    pub space_id: Option<u32>,
+    pub timestamp: u64,
+    pub signature: String,
+}
+
+impl Storable for Delegation {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// Setting `delegate` to `None` revokes the delegation.
+#[derive(CandidType, Deserialize, Debug, Clone, Default, Serialize)]
+pub struct DelegationMessage {
+    pub delegator: String,
+    pub delegate: Option<String>,
+    pub space_id: Option<u32>,
+    pub timestamp: u64,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, Default)]
+pub struct DelegationData {
+    pub signature: String,
+    pub message: DelegationMessage,
+}
+
+/// Power a delegator contributed to a delegate's vote.
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct DelegatedPower {
+    pub delegator: String,
+    pub voting_power: Nat,
+}
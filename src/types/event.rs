@@ -1,5 +1,6 @@
 use candid::{CandidType, Decode, Deserialize, Encode};
 use ic_stable_structures::{storable::Bound, Storable};
+use serde::Serialize;
 use std::borrow::Cow;
 
 use super::{evm_event::EvmEvent, webhook_event::WebhookEvent};
@@ -26,14 +27,34 @@ impl Storable for Event {
     const BOUND: Bound = Bound::Unbounded;
 }
 
-#[derive(CandidType, Deserialize, PartialEq, Eq, Debug, Clone)]
+impl Event {
+    pub fn redacted(self) -> Self {
+        let data = match self.data {
+            EventData::Webhook(webhook) => EventData::Webhook(webhook.redacted()),
+            data => data,
+        };
+
+        Event { data, ..self }
+    }
+}
+
+#[derive(CandidType, Deserialize, PartialEq, Eq, Debug, Clone, Serialize)]
 pub enum EventTrigger {
     ProposalCreated,
     ProposalEnded,
+    // Fires the space's `ending_soon_lead_time` seconds before voting ends
+    ProposalEndingSoon,
     Vote,
+    VotingStarted,
+    // The first vote that brings the turnout up to the space's quorum
+    QuorumReached,
+    ProposalExecuted,
+    ProposalCancelled,
+    StrategyChanged,
+    SpaceUpdated,
 }
 
-#[derive(CandidType, Deserialize, Debug, Clone)]
+#[derive(CandidType, Deserialize, Debug, Clone, Serialize)]
 pub enum EventData {
     Evm(EvmEvent),
     Webhook(WebhookEvent)
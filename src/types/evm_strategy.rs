@@ -1,10 +1,11 @@
 use candid::{CandidType, Decode, Deserialize, Encode};
 use ic_stable_structures::{storable::Bound, Storable};
+use serde::Serialize;
 use std::borrow::Cow;
 
 const MAX_VALUE_SIZE: u32 = 1000;
 
-#[derive(CandidType, Deserialize, Debug, Clone)]
+#[derive(CandidType, Deserialize, Debug, Clone, Serialize)]
 pub struct EvmStrategy {
     pub strategy_id: u32,
     pub chain_id: u64,
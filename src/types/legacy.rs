@@ -0,0 +1,297 @@
+use candid::{CandidType, Deserialize, Nat};
+
+use crate::services::lifecycle::{current_status, tally};
+
+use super::event::{Event, EventData, EventTrigger};
+use super::evm_event::EvmEvent;
+use super::proposal::{Proposal, ProposalStatus};
+use super::proposal_option_vote::ProposalOptionVote;
+use super::proposal_options::ProposalOption;
+use super::space::{Ownership, Space};
+use super::strategy::Strategy;
+use super::webhook_event::WebhookEvent;
+
+/// Space as stored before proposals had a lifecycle. Spaces saved in this
+/// shape are converted when they are read.
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct SpaceV0 {
+    pub id: u32,
+    pub name: String,
+    pub icon_link: String,
+    pub website_link: String,
+    pub owner_address: String,
+    pub vote_delay: u32,
+    pub vote_duration: u32,
+    pub min_vote_role: u32,
+    pub min_vote_power: Nat,
+    pub quorum: Nat,
+    pub proposals: Vec<ProposalV0>,
+    pub strategies: Vec<Strategy>,
+    pub events: Vec<EventV0>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct ProposalV0 {
+    pub id: u32,
+    pub title: String,
+    pub description: String,
+    pub date_created: u64,
+    pub mechanism: u32,
+    pub space_id: u32,
+    pub options: Vec<ProposalOptionV0>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct ProposalOptionV0 {
+    pub id: u32,
+    pub name: String,
+    pub proposal_id: u32,
+    pub on_win_contract_address: String,
+    pub on_win_bytecode: String,
+    pub on_win_chain_id: u32,
+    pub votes: Vec<ProposalOptionVoteV0>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct ProposalOptionVoteV0 {
+    pub id: u32,
+    pub user_address: String,
+    pub vote_type: u32,
+    pub timestamp: u64,
+    pub signature: String,
+    pub voting_power: Nat,
+    pub option_id: u32,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct EventV0 {
+    pub event_trigger: EventTrigger,
+    pub space_id: u32,
+    pub data: EventDataV0,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub enum EventDataV0 {
+    Evm(EvmEvent),
+    Webhook(WebhookEventV0),
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct WebhookEventV0 {
+    pub webhook_url: String,
+    pub payload: String,
+}
+
+impl SpaceV0 {
+    /// Converts the space to the current layout. Proposals whose voting ended
+    /// before `now` are settled on the spot, without notifying the space or
+    /// queueing their execution, so an upgrade doesn't announce old results.
+    pub fn migrate(self, now: u64) -> Space {
+        let (vote_delay, vote_duration) = (self.vote_delay as u64, self.vote_duration as u64);
+
+        let mut space = Space {
+            id: self.id,
+            name: self.name,
+            icon_link: self.icon_link,
+            website_link: self.website_link,
+            owner_address: self.owner_address,
+            ownership: Ownership::default(),
+            vote_delay: self.vote_delay,
+            vote_duration: self.vote_duration,
+            min_vote_duration: 0,
+            max_vote_duration: 0,
+            // The old role number had no meaning yet, so anyone may vote
+            min_vote_role: None,
+            min_proposal_role: None,
+            min_vote_power: self.min_vote_power,
+            quorum: self.quorum,
+            allow_vote_change: false,
+            min_proposal_power: Nat::from(0_u32),
+            proposer_allowlist: Vec::new(),
+            guardian_address: None,
+            execution_delay: 0,
+            ending_soon_lead_time: 0,
+            proposals: self
+                .proposals
+                .into_iter()
+                .map(|p| p.migrate(vote_delay, vote_duration))
+                .collect(),
+            strategies: self.strategies,
+            events: self.events.into_iter().map(Event::from).collect(),
+            templates: Vec::new(),
+        };
+
+        let statuses: Vec<ProposalStatus> = space
+            .proposals
+            .iter()
+            .map(|p| match current_status(p, now) {
+                ProposalStatus::Closed => tally(&space, p),
+                status => status,
+            })
+            .collect();
+        for (proposal, status) in space.proposals.iter_mut().zip(statuses) {
+            proposal.status = status;
+        }
+
+        space
+    }
+}
+
+impl ProposalV0 {
+    /// Old proposals used the space's voting window from their creation.
+    fn migrate(self, vote_delay: u64, vote_duration: u64) -> Proposal {
+        let vote_start = self.date_created + vote_delay;
+
+        Proposal {
+            id: self.id,
+            title: self.title,
+            description: self.description,
+            date_created: self.date_created,
+            mechanism: self.mechanism,
+            space_id: self.space_id,
+            options: self.options.into_iter().map(ProposalOption::from).collect(),
+            status: ProposalStatus::Pending,
+            vote_start,
+            vote_end: vote_start + vote_duration,
+            author: String::new(),
+            signature: String::new(),
+            queued_at: None,
+            eta: None,
+            execution_results: Vec::new(),
+            content_hash: None,
+            template_id: None,
+            metadata: Default::default(),
+            snapshot_blocks: None,
+        }
+    }
+}
+
+impl From<ProposalOptionV0> for ProposalOption {
+    fn from(option: ProposalOptionV0) -> Self {
+        ProposalOption {
+            id: option.id,
+            name: option.name,
+            proposal_id: option.proposal_id,
+            // The old on-win fields were never executed. Turning them into
+            // actions would send them as soon as the proposal is finalized.
+            on_win_actions: Vec::new(),
+            settings_changes: Vec::new(),
+            votes: option
+                .votes
+                .into_iter()
+                .map(ProposalOptionVote::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<ProposalOptionVoteV0> for ProposalOptionVote {
+    fn from(vote: ProposalOptionVoteV0) -> Self {
+        ProposalOptionVote {
+            id: vote.id,
+            user_address: vote.user_address,
+            vote_type: vote.vote_type,
+            timestamp: vote.timestamp,
+            signature: vote.signature,
+            voting_power: vote.voting_power,
+            option_id: vote.option_id,
+            delegated_power: Vec::new(),
+            reason: None,
+            message_timestamp: None,
+        }
+    }
+}
+
+impl From<EventV0> for Event {
+    fn from(event: EventV0) -> Self {
+        let data = match event.data {
+            EventDataV0::Evm(evm_event) => EventData::Evm(evm_event),
+            EventDataV0::Webhook(webhook) => EventData::Webhook(WebhookEvent {
+                webhook_url: webhook.webhook_url,
+                payload: webhook.payload,
+                secret: None,
+                headers: Vec::new(),
+            }),
+        };
+
+        Event {
+            event_trigger: event.event_trigger,
+            space_id: event.space_id,
+            data,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proposal(id: u32, date_created: u64, votes: Vec<ProposalOptionVoteV0>) -> ProposalV0 {
+        ProposalV0 {
+            id,
+            title: String::new(),
+            description: String::new(),
+            date_created,
+            mechanism: 0,
+            space_id: 1,
+            options: vec![ProposalOptionV0 {
+                id: 1,
+                name: "Yes".to_owned(),
+                proposal_id: id,
+                on_win_contract_address: "0x0000000000000000000000000000000000000001".to_owned(),
+                on_win_bytecode: "0x12345678".to_owned(),
+                on_win_chain_id: 1,
+                votes,
+            }],
+        }
+    }
+
+    fn vote(voting_power: u32) -> ProposalOptionVoteV0 {
+        ProposalOptionVoteV0 {
+            id: 1,
+            user_address: "0xabc".to_owned(),
+            vote_type: 0,
+            timestamp: 0,
+            signature: String::new(),
+            voting_power: Nat::from(voting_power),
+            option_id: 1,
+        }
+    }
+
+    #[test]
+    fn settles_ended_proposals_without_queueing_them() {
+        let space = SpaceV0 {
+            id: 1,
+            name: "Space".to_owned(),
+            icon_link: String::new(),
+            website_link: String::new(),
+            owner_address: "0xowner".to_owned(),
+            vote_delay: 10,
+            vote_duration: 100,
+            min_vote_role: 0,
+            min_vote_power: Nat::from(0_u32),
+            quorum: Nat::from(5_u32),
+            proposals: vec![
+                proposal(1, 0, vec![vote(10)]),
+                proposal(2, 0, vec![vote(1)]),
+                proposal(3, 1_000, vec![]),
+            ],
+            strategies: Vec::new(),
+            events: Vec::new(),
+        };
+
+        let space = space.migrate(1_000);
+        let statuses: Vec<ProposalStatus> = space.proposals.iter().map(|p| p.status).collect();
+
+        assert_eq!(
+            statuses,
+            [
+                ProposalStatus::Succeeded,
+                ProposalStatus::QuorumNotMet,
+                ProposalStatus::Pending
+            ]
+        );
+        assert!(space.proposals.iter().all(|p| p.eta.is_none()));
+        assert!(space.proposals[0].options[0].on_win_actions.is_empty());
+    }
+}
@@ -9,4 +9,15 @@ pub mod event;
 pub mod eth_rpc;
 pub mod btc_strategy;
 pub mod evm_event;
-pub mod webhook_event;
\ No newline at end of file
+pub mod webhook_event;
+pub mod voting_power_cache;
+pub mod delegation;
+pub mod on_win_action;
+pub mod comment;
+pub mod proposal_template;
+pub mod proposal_metadata;
+pub mod role;
+pub mod settings_change;
+pub mod subscription;
+pub mod webhook_delivery;
+pub mod legacy;
@@ -0,0 +1,19 @@
+use candid::{CandidType, Deserialize, Nat};
+use serde::Serialize;
+
+/// Transaction sent by the canister when the option carrying it wins.
+#[derive(CandidType, Deserialize, Debug, Clone, Serialize)]
+pub struct OnWinAction {
+    pub target: String,
+    #[serde(serialize_with = "crate::utils::serialize_nat")]
+    pub value: Nat,
+    pub calldata: String,
+    pub chain_id: u64,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct ActionResult {
+    pub action_index: u32,
+    pub tx_hash: Option<String>,
+    pub error: Option<String>,
+}
@@ -1,23 +1,48 @@
 use candid::{CandidType, Decode, Deserialize, Encode};
 use ic_stable_structures::{storable::Bound, Storable};
+use serde::Serialize;
 use std::borrow::Cow;
 
-use super::proposal_options::ProposalOption;
+use super::on_win_action::ActionResult;
+use super::proposal_metadata::ProposalMetadata;
+use super::proposal_options::{InsertProposalOption, ProposalOption, ProposalOptionSummary};
 
 const MAX_VALUE_SIZE: u32 = 1000;
+pub const MAX_DESCRIPTION_LENGTH: usize = 20_000;
 
 #[derive(CandidType, Deserialize, Debug, Clone)]
 pub struct Proposal {
    pub id: u32,
    pub title: String,
+   // Markdown body, at most MAX_DESCRIPTION_LENGTH bytes
    pub description: String,
    pub date_created: u64,
    pub mechanism: u32,
    pub space_id: u32,
    pub options: Vec<ProposalOption>,
+   pub status: ProposalStatus,
+   pub vote_start: u64,
+   pub vote_end: u64,
+   pub author: String,
+   pub signature: String,
+   pub queued_at: Option<u64>,
+   pub eta: Option<u64>,
+   pub execution_results: Vec<ActionResult>,
+   // keccak256 of the proposal content, taken when voting opens
+   pub content_hash: Option<String>,
+   pub template_id: Option<u32>,
+   pub metadata: ProposalMetadata,
+   // Blocks voting power is read at, pinned when the proposal is created
+   pub snapshot_blocks: Option<Vec<SnapshotBlock>>,
 }
 
 
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotBlock {
+    pub chain_id: u64,
+    pub block: u64,
+}
+
 impl Storable for Proposal {
     fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
         Cow::Owned(Encode!(self).unwrap())
@@ -29,3 +54,145 @@ impl Storable for Proposal {
 
     const BOUND: Bound = Bound::Unbounded;
 }
+
+#[derive(CandidType, Deserialize, Debug, Clone, Default, Serialize)]
+pub struct ProposalMessage {
+    pub space_id: u32,
+    pub address: String,
+    pub title: String,
+    pub description: String,
+    pub mechanism: u32,
+    pub options: Vec<InsertProposalOption>,
+    pub draft: bool,
+    pub vote_start: Option<u64>,
+    pub vote_end: Option<u64>,
+    // Left out of the signed JSON when unset, like the optional vote fields
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template_id: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<ProposalMetadata>,
+    // Must exceed the author's previous signed request, so the message can't
+    // be submitted twice
+    pub timestamp: u64,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, Default)]
+pub struct ProposalData {
+    pub signature: String,
+    pub message: ProposalMessage,
+}
+
+#[derive(CandidType, Deserialize, PartialEq, Eq, Debug, Clone, Copy, Serialize)]
+pub enum ProposalAction {
+    Cancel,
+    Veto,
+}
+
+/// Signed request to act on an existing proposal. The action is part of the
+/// signed message so a signature can't be reused for another action.
+#[derive(CandidType, Deserialize, Debug, Clone, Serialize)]
+pub struct ProposalActionMessage {
+    pub space_id: u32,
+    pub proposal_id: u32,
+    pub address: String,
+    pub action: ProposalAction,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct ProposalActionData {
+    pub signature: String,
+    pub message: ProposalActionMessage,
+}
+
+#[derive(CandidType, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum ProposalStatus {
+    Draft,
+    Pending,
+    Active,
+    Closed,
+    Succeeded,
+    Defeated,
+    QuorumNotMet,
+    Executed,
+    Cancelled,
+    Vetoed,
+}
+
+impl ProposalStatus {
+    pub fn can_transition_to(&self, next: ProposalStatus) -> bool {
+        use ProposalStatus::*;
+
+        matches!(
+            (self, next),
+            (Draft, Pending)
+                | (Draft, Cancelled)
+                | (Pending, Active)
+                | (Pending, Cancelled)
+                | (Active, Closed)
+                | (Active, Cancelled)
+                | (Active, Vetoed)
+                | (Closed, Succeeded)
+                | (Closed, Defeated)
+                | (Closed, QuorumNotMet)
+                | (Closed, Vetoed)
+                | (Succeeded, Executed)
+                | (Succeeded, Vetoed)
+        )
+    }
+}
+
+/// Proposal without the votes of its options, for listings.
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct ProposalSummary {
+    pub id: u32,
+    pub space_id: u32,
+    pub title: String,
+    pub mechanism: u32,
+    pub status: ProposalStatus,
+    pub date_created: u64,
+    pub vote_start: u64,
+    pub vote_end: u64,
+    pub author: String,
+    pub template_id: Option<u32>,
+    pub metadata: ProposalMetadata,
+    pub options: Vec<ProposalOptionSummary>,
+}
+
+/// Unset fields don't filter. Dates bound `date_created` and are inclusive.
+#[derive(CandidType, Deserialize, Debug, Clone, Default)]
+pub struct ProposalFilter {
+    pub status: Option<ProposalStatus>,
+    pub author: Option<String>,
+    pub created_after: Option<u64>,
+    pub created_before: Option<u64>,
+}
+
+/// `next_cursor` is passed back to fetch the following page and is `None`
+/// on the last one.
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct ProposalPage {
+    pub proposals: Vec<ProposalSummary>,
+    pub next_cursor: Option<u32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProposalStatus::*;
+
+    #[test]
+    fn allows_only_lifecycle_transitions() {
+        assert!(Draft.can_transition_to(Pending));
+        assert!(Pending.can_transition_to(Active));
+        assert!(Active.can_transition_to(Closed));
+        assert!(Closed.can_transition_to(Succeeded));
+        assert!(Succeeded.can_transition_to(Executed));
+        assert!(Succeeded.can_transition_to(Vetoed));
+
+        assert!(!Draft.can_transition_to(Active));
+        assert!(!Pending.can_transition_to(Closed));
+        assert!(!Closed.can_transition_to(Cancelled));
+        assert!(!Defeated.can_transition_to(Succeeded));
+        assert!(!Executed.can_transition_to(Vetoed));
+        assert!(!Active.can_transition_to(Active));
+    }
+}
@@ -0,0 +1,83 @@
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+
+const MAX_TAGS: usize = 10;
+const MAX_TAG_LENGTH: usize = 32;
+const MAX_ATTACHMENTS: usize = 20;
+const MAX_URL_LENGTH: usize = 512;
+
+/// Structured details of a proposal next to its markdown description.
+/// Attachments are IPFS CIDs, so their content can be pinned and checked
+/// against the proposal's content hash.
+#[derive(CandidType, Deserialize, Debug, Clone, Default, Serialize)]
+pub struct ProposalMetadata {
+    pub tags: Vec<String>,
+    pub discussion_url: Option<String>,
+    pub attachments: Vec<String>,
+}
+
+impl ProposalMetadata {
+    /// Validates the metadata and returns it with tags lowercased, trimmed
+    /// and deduplicated.
+    pub fn normalized(self) -> Result<Self, String> {
+        let mut tags: Vec<String> = Vec::new();
+        for tag in self.tags {
+            let tag = tag.trim().to_lowercase();
+            if tag.is_empty() || tag.len() > MAX_TAG_LENGTH {
+                return Err(format!(
+                    "Tags must have between 1 and {} characters",
+                    MAX_TAG_LENGTH
+                ));
+            }
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+
+        if tags.len() > MAX_TAGS {
+            return Err(format!("A proposal can have at most {} tags", MAX_TAGS));
+        }
+
+        if let Some(ref discussion_url) = self.discussion_url {
+            let valid = discussion_url.len() <= MAX_URL_LENGTH
+                && url::Url::parse(discussion_url)
+                    .map(|url| url.scheme() == "https" || url.scheme() == "http")
+                    .unwrap_or(false);
+            if !valid {
+                return Err("Invalid discussion url".to_owned());
+            }
+        }
+
+        if self.attachments.len() > MAX_ATTACHMENTS {
+            return Err(format!(
+                "A proposal can have at most {} attachments",
+                MAX_ATTACHMENTS
+            ));
+        }
+
+        if let Some(cid) = self.attachments.iter().find(|cid| !is_ipfs_cid(cid)) {
+            return Err(format!("Invalid IPFS CID: {}", cid));
+        }
+
+        Ok(ProposalMetadata {
+            tags,
+            discussion_url: self.discussion_url,
+            attachments: self.attachments,
+        })
+    }
+}
+
+/// Accepts base58 CIDv0 (`Qm...`) and base32 CIDv1 (`b...`), the forms IPFS
+/// gateways and pinning services hand out.
+fn is_ipfs_cid(cid: &str) -> bool {
+    const BASE58: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+    if cid.len() == 46 && cid.starts_with("Qm") {
+        return cid.chars().all(|c| BASE58.contains(c));
+    }
+
+    cid.len() > 8
+        && cid.len() <= 128
+        && cid.starts_with('b')
+        && cid.chars().all(|c| c.is_ascii_lowercase() || ('2'..='7').contains(&c))
+}
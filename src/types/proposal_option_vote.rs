@@ -2,6 +2,8 @@ use candid::{CandidType, Decode, Deserialize, Encode, Nat};
 use ic_stable_structures::{storable::Bound, Storable};
 use std::borrow::Cow;
 
+use super::delegation::DelegatedPower;
+
 const MAX_VALUE_SIZE: u32 = 1000;
 
 #[derive(CandidType, Deserialize, Debug, Clone)]
@@ -14,6 +16,9 @@ pub struct ProposalOptionVote {
     pub signature: String,
     pub voting_power: Nat,
     pub option_id: u32,
+    pub delegated_power: Vec<DelegatedPower>,
+    pub reason: Option<String>,
+    pub message_timestamp: Option<u64>,
 }
 
 impl Storable for ProposalOptionVote {
@@ -27,3 +32,78 @@ impl Storable for ProposalOptionVote {
 
     const BOUND: Bound = Bound::Unbounded;
 }
+
+/// Unset fields don't filter.
+#[derive(CandidType, Deserialize, Debug, Clone, Default)]
+pub struct VoteFilter {
+    pub address: Option<String>,
+    pub option_id: Option<u32>,
+    pub min_power: Option<Nat>,
+}
+
+/// Position after the last returned vote. Votes are ordered by option and
+/// then by vote id.
+#[derive(CandidType, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct VoteCursor {
+    pub option_id: u32,
+    pub vote_id: u32,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct VotePage {
+    pub votes: Vec<ProposalOptionVote>,
+    pub next_cursor: Option<VoteCursor>,
+}
+
+const MAX_KEY_SIZE: u32 = 200;
+
+/// Entry of the index of votes by voter address. Addresses are lowercase so
+/// all votes of an address are next to each other.
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AddressVoteKey {
+    pub address: String,
+    pub space_id: u32,
+    pub proposal_id: u32,
+    pub option_id: u32,
+    pub vote_id: u32,
+}
+
+impl Storable for AddressVoteKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: MAX_KEY_SIZE,
+        is_fixed_size: false,
+    };
+}
+
+/// Position after the last returned vote of an address. Votes are ordered by
+/// space, proposal, option and vote id.
+#[derive(CandidType, Deserialize, Debug, Clone, Copy)]
+pub struct AddressVoteCursor {
+    pub space_id: u32,
+    pub proposal_id: u32,
+    pub option_id: u32,
+    pub vote_id: u32,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct AddressVote {
+    pub space_id: u32,
+    pub proposal_id: u32,
+    pub proposal_title: String,
+    pub option_name: String,
+    pub vote: ProposalOptionVote,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct AddressVotePage {
+    pub votes: Vec<AddressVote>,
+    pub next_cursor: Option<AddressVoteCursor>,
+}
@@ -1,8 +1,11 @@
-use candid::{CandidType, Decode, Deserialize, Encode};
+use candid::{CandidType, Decode, Deserialize, Encode, Nat};
 use ic_stable_structures::{storable::Bound, Storable};
+use serde::Serialize;
 use std::borrow::Cow;
 
+use super::on_win_action::OnWinAction;
 use super::proposal_option_vote::ProposalOptionVote;
+use super::settings_change::SettingsChange;
 
 const MAX_VALUE_SIZE: u32 = 1000;
 
@@ -12,9 +15,10 @@ pub struct ProposalOption {
     pub id: u32,
     pub name: String,
     pub proposal_id: u32,
-    pub on_win_contract_address: String,
-    pub on_win_bytecode: String,
-    pub on_win_chain_id: u32,
+    // Executed in order when this option wins
+    pub on_win_actions: Vec<OnWinAction>,
+    // Applied to the space once the on-win actions went through
+    pub settings_changes: Vec<SettingsChange>,
     pub votes: Vec<ProposalOptionVote>,
 }
 
@@ -30,10 +34,12 @@ impl Storable for ProposalOption {
     const BOUND: Bound = Bound::Unbounded;
 }
 
-#[derive(CandidType, Deserialize, Debug, Clone)]
+#[derive(CandidType, Deserialize, Debug, Clone, Serialize)]
 
 pub struct InsertProposalOption {
     pub name: String,
+    pub on_win_actions: Vec<OnWinAction>,
+    pub settings_changes: Vec<SettingsChange>,
 }
 
 impl Storable for InsertProposalOption {
@@ -45,8 +51,14 @@ impl Storable for InsertProposalOption {
         Decode!(bytes.as_ref(), Self).unwrap()
     }
 
-    const BOUND: Bound = Bound::Bounded {
-        max_size: MAX_VALUE_SIZE,
-        is_fixed_size: false,
-    };
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// Option without its votes, with their count and total power instead.
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct ProposalOptionSummary {
+    pub id: u32,
+    pub name: String,
+    pub vote_count: u32,
+    pub voting_power: Nat,
 }
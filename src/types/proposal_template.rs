@@ -0,0 +1,36 @@
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+
+use super::proposal_options::InsertProposalOption;
+
+/// Preset for proposals of one kind, e.g. treasury transfers. Proposals
+/// created from a template must use its mechanism and contain its required
+/// sections; options and voting window default to the template's.
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct ProposalTemplate {
+    pub id: u32,
+    pub name: String,
+    pub mechanism: u32,
+    // Used when a proposal is submitted without options, on-win actions included
+    pub options: Vec<InsertProposalOption>,
+    // Override the space's vote_delay and vote_duration when set
+    pub vote_delay: Option<u32>,
+    pub vote_duration: Option<u32>,
+    // Markdown skeleton offered to authors as a starting point
+    pub body: String,
+    // Headings the description of a proposal must contain
+    pub required_sections: Vec<String>,
+    // Timestamp of the authorization of the last change
+    pub updated_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, Default, Serialize)]
+pub struct InsertProposalTemplate {
+    pub name: String,
+    pub mechanism: u32,
+    pub options: Vec<InsertProposalOption>,
+    pub vote_delay: Option<u32>,
+    pub vote_duration: Option<u32>,
+    pub body: String,
+    pub required_sections: Vec<String>,
+}
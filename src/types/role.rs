@@ -0,0 +1,145 @@
+use candid::{CandidType, Decode, Deserialize, Encode};
+use ic_stable_structures::{storable::Bound, Storable};
+use serde::Serialize;
+use std::borrow::Cow;
+
+const MAX_KEY_SIZE: u32 = 100;
+
+/// Built-in roles are ranked Admin > Moderator > Author > Member and include
+/// the permissions of the roles below them. Custom roles only match
+/// themselves and are meant for `min_vote_role` and `min_proposal_role`.
+#[derive(CandidType, Deserialize, Serialize, PartialEq, Eq, Debug, Clone)]
+pub enum SpaceRole {
+    Admin,
+    Moderator,
+    Author,
+    Member,
+    Custom(String),
+}
+
+impl SpaceRole {
+    /// Whether holding this role satisfies a requirement for `required`.
+    pub fn grants(&self, required: &SpaceRole) -> bool {
+        match (self.rank(), required.rank()) {
+            (Some(rank), Some(required_rank)) => rank >= required_rank,
+            _ => self == required,
+        }
+    }
+
+    fn rank(&self) -> Option<u8> {
+        match self {
+            SpaceRole::Admin => Some(4),
+            SpaceRole::Moderator => Some(3),
+            SpaceRole::Author => Some(2),
+            SpaceRole::Member => Some(1),
+            SpaceRole::Custom(_) => None,
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MemberKey {
+    pub space_id: u32,
+    pub address: String,
+}
+
+impl Storable for MemberKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: MAX_KEY_SIZE,
+        is_fixed_size: false,
+    };
+}
+
+/// Roles of an address in a space. The record also keeps the timestamp of
+/// the last signed request of the address, which later requests must exceed
+/// so that signatures can't be replayed.
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct SpaceMember {
+    pub space_id: u32,
+    pub address: String,
+    pub roles: Vec<SpaceRole>,
+    pub last_timestamp: u64,
+}
+
+impl Storable for SpaceMember {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// Signed by an Admin to replace the roles of `member`. An empty list removes
+/// the member from the space.
+#[derive(CandidType, Deserialize, Debug, Clone, Default, Serialize)]
+pub struct RoleMessage {
+    pub space_id: u32,
+    pub address: String,
+    pub member: String,
+    pub roles: Vec<SpaceRole>,
+    pub timestamp: u64,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, Default)]
+pub struct RoleData {
+    pub signature: String,
+    pub message: RoleMessage,
+    pub cosignatures: Vec<Cosignature>,
+}
+
+/// Signature of another owner over the same message, for spaces whose admin
+/// actions need several owners.
+#[derive(CandidType, Deserialize, Debug, Clone, Default)]
+pub struct Cosignature {
+    pub address: String,
+    pub signature: String,
+}
+
+/// Signature of `address` authorizing one call to a mutating endpoint. The
+/// signed message is the JSON object `{"address","args","method","space_id",
+/// "timestamp"}`, where `args` is an array of the call's other arguments in
+/// order, and `timestamp` must be newer than the address' last request in
+/// the space. Cosigners sign the same message.
+#[derive(CandidType, Deserialize, Debug, Clone, Default)]
+pub struct Authorization {
+    pub address: String,
+    pub timestamp: u64,
+    pub signature: String,
+    pub cosignatures: Vec<Cosignature>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SpaceRole::*;
+
+    #[test]
+    fn built_in_roles_grant_the_ones_below() {
+        assert!(Admin.grants(&Member));
+        assert!(Moderator.grants(&Author));
+        assert!(Author.grants(&Author));
+        assert!(!Member.grants(&Author));
+        assert!(!Moderator.grants(&Admin));
+    }
+
+    #[test]
+    fn custom_roles_only_match_themselves() {
+        let delegate = Custom("delegate".to_owned());
+
+        assert!(delegate.grants(&Custom("delegate".to_owned())));
+        assert!(!delegate.grants(&Custom("council".to_owned())));
+        assert!(!delegate.grants(&Member));
+        assert!(!Admin.grants(&delegate));
+    }
+}
@@ -0,0 +1,32 @@
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+
+use super::event::{EventData, EventTrigger};
+use super::evm_strategy::EvmStrategy;
+use super::space::InsertSpace;
+
+/// Change to the space applied by the canister when the option carrying it
+/// wins, mirroring the admin endpoint of the same name. This lets spaces be
+/// governed by their own proposals.
+#[derive(CandidType, Deserialize, Debug, Clone, Serialize)]
+pub enum SettingsChange {
+    UpdateSpace(InsertSpace),
+    InsertEvmStrategy {
+        name: String,
+        description: String,
+        evm_strategy: EvmStrategy,
+    },
+    UpdateEvmStrategy {
+        strategy_id: u32,
+        name: String,
+        description: String,
+        evm_strategy: EvmStrategy,
+    },
+    DeleteStrategy {
+        strategy_id: u32,
+    },
+    InsertEvent {
+        event_trigger: EventTrigger,
+        data: EventData,
+    },
+}
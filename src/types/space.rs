@@ -1,9 +1,14 @@
 use candid::{CandidType, Decode, Deserialize, Encode, Nat};
 use ic_stable_structures::{storable::Bound, Storable};
+use serde::Serialize;
 use std::borrow::Cow;
 
 use super::proposal::Proposal;
 use super::event::Event;
+use super::legacy::SpaceV0;
+use crate::services::lifecycle::now;
+use super::proposal_template::ProposalTemplate;
+use super::role::SpaceRole;
 use super::strategy::Strategy;
 
 const MAX_VALUE_SIZE: u32 = 400;
@@ -15,14 +20,31 @@ pub struct Space {
     pub icon_link: String,
     pub website_link: String,
     pub owner_address: String,
+    pub ownership: Ownership,
     pub vote_delay: u32,
     pub vote_duration: u32,
-    pub min_vote_role: u32,
+    // Bounds for proposals that set their own voting window, 0 means no maximum
+    pub min_vote_duration: u32,
+    pub max_vote_duration: u32,
+    // Roles needed to vote and to create proposals, anyone when unset
+    pub min_vote_role: Option<SpaceRole>,
+    pub min_proposal_role: Option<SpaceRole>,
     pub min_vote_power: Nat,
     pub quorum: Nat,
+    pub allow_vote_change: bool,
+    pub min_proposal_power: Nat,
+    // Addresses allowed to create proposals, anyone may propose when empty
+    pub proposer_allowlist: Vec<String>,
+    // Address that may veto proposals before their result is carried out
+    pub guardian_address: Option<String>,
+    // Seconds a succeeded proposal waits before it can be executed
+    pub execution_delay: u32,
+    // Seconds before the end of voting to notify ProposalEndingSoon, 0 disables it
+    pub ending_soon_lead_time: u32,
     pub proposals: Vec<Proposal>,
     pub strategies: Vec<Strategy>,
     pub events: Vec<Event>,
+    pub templates: Vec<ProposalTemplate>,
 }
 
 impl Storable for Space {
@@ -31,8 +53,151 @@ impl Storable for Space {
     }
 
     fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
-        Decode!(bytes.as_ref(), Self).unwrap()
+        // Spaces saved before the current layout are converted on the fly
+        Decode!(bytes.as_ref(), Self)
+            .unwrap_or_else(|_| Decode!(bytes.as_ref(), SpaceV0).unwrap().migrate(now()))
     }
 
     const BOUND: Bound = Bound::Unbounded;
 }
+
+/// Settings of a space as passed to `insert_space` and `update_space`.
+#[derive(CandidType, Deserialize, Debug, Clone, Serialize)]
+pub struct InsertSpace {
+    pub name: String,
+    pub icon_link: String,
+    pub website_link: String,
+    pub owner_address: String,
+    pub vote_delay: u32,
+    pub vote_duration: u32,
+    pub min_vote_duration: u32,
+    pub max_vote_duration: u32,
+    pub min_vote_role: Option<SpaceRole>,
+    pub min_proposal_role: Option<SpaceRole>,
+    #[serde(serialize_with = "crate::utils::serialize_nat")]
+    pub min_vote_power: Nat,
+    #[serde(serialize_with = "crate::utils::serialize_nat")]
+    pub quorum: Nat,
+    pub allow_vote_change: bool,
+    #[serde(serialize_with = "crate::utils::serialize_nat")]
+    pub min_proposal_power: Nat,
+    pub proposer_allowlist: Vec<String>,
+    pub guardian_address: Option<String>,
+    pub execution_delay: u32,
+    pub ending_soon_lead_time: u32,
+}
+
+impl Space {
+    pub fn new(
+        id: u32,
+        settings: InsertSpace,
+        proposals: Vec<Proposal>,
+        strategies: Vec<Strategy>,
+        events: Vec<Event>,
+        templates: Vec<ProposalTemplate>,
+    ) -> Self {
+        Space {
+            id,
+            name: settings.name,
+            icon_link: settings.icon_link,
+            website_link: settings.website_link,
+            owner_address: settings.owner_address,
+            ownership: Ownership::default(),
+            vote_delay: settings.vote_delay,
+            vote_duration: settings.vote_duration,
+            min_vote_duration: settings.min_vote_duration,
+            max_vote_duration: settings.max_vote_duration,
+            min_vote_role: settings.min_vote_role,
+            min_proposal_role: settings.min_proposal_role,
+            min_vote_power: settings.min_vote_power,
+            quorum: settings.quorum,
+            allow_vote_change: settings.allow_vote_change,
+            min_proposal_power: settings.min_proposal_power,
+            proposer_allowlist: settings.proposer_allowlist,
+            guardian_address: settings.guardian_address,
+            execution_delay: settings.execution_delay,
+            ending_soon_lead_time: settings.ending_soon_lead_time,
+            proposals,
+            strategies,
+            events,
+            templates,
+        }
+    }
+
+    /// The space without its webhook secrets, for query results.
+    pub fn redacted(mut self) -> Self {
+        self.events = self.events.into_iter().map(Event::redacted).collect();
+        self
+    }
+}
+
+/// Owners besides `owner_address` and the number of owner signatures admin
+/// actions need. With a threshold of one, every owner acts alone.
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct Ownership {
+    // Address that becomes the owner once it accepts the transfer
+    pub pending_owner: Option<String>,
+    pub co_owners: Vec<String>,
+    pub threshold: u32,
+}
+
+impl Default for Ownership {
+    fn default() -> Self {
+        Ownership {
+            pending_owner: None,
+            co_owners: Vec::new(),
+            threshold: 1,
+        }
+    }
+}
+
+/// Signed by the pending owner to accept a space transfer.
+#[derive(CandidType, Deserialize, Debug, Clone, Default, Serialize)]
+pub struct AcceptOwnershipMessage {
+    pub space_id: u32,
+    pub address: String,
+    pub timestamp: u64,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, Default)]
+pub struct AcceptOwnershipData {
+    pub signature: String,
+    pub message: AcceptOwnershipMessage,
+}
+
+/// Lightweight view of a space for listings, without its proposals.
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct SpaceSummary {
+    pub id: u32,
+    pub name: String,
+    pub icon_link: String,
+    pub website_link: String,
+    pub owner_address: String,
+    pub proposal_count: u32,
+    pub member_count: u32,
+    // Latest proposal creation or vote, 0 for a space without either
+    pub last_activity: u64,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpaceSort {
+    Id,
+    Name,
+    ProposalCount,
+    MemberCount,
+    LastActivity,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct SpaceFilter {
+    // Case-insensitive part of the space name
+    pub name: Option<String>,
+    pub sort: SpaceSort,
+    pub descending: bool,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct SpacePage {
+    pub spaces: Vec<SpaceSummary>,
+    pub next_cursor: Option<u32>,
+}
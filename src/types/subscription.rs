@@ -0,0 +1,91 @@
+use candid::{CandidType, Decode, Deserialize, Encode};
+use ic_stable_structures::{storable::Bound, Storable};
+use serde::Serialize;
+use std::borrow::Cow;
+
+use super::event::EventTrigger;
+use super::webhook_event::WebhookEvent;
+
+const MAX_KEY_SIZE: u32 = 100;
+
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SubscriptionKey {
+    pub space_id: u32,
+    pub address: String,
+}
+
+impl Storable for SubscriptionKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: MAX_KEY_SIZE,
+        is_fixed_size: false,
+    };
+}
+
+/// An address following a space. Followers with a webhook are notified of
+/// the triggers they subscribed to, the same way as the space's own webhook
+/// events.
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct Subscription {
+    pub space_id: u32,
+    pub address: String,
+    pub triggers: Vec<EventTrigger>,
+    pub webhook: Option<WebhookEvent>,
+    pub timestamp: u64,
+}
+
+impl Storable for Subscription {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl Subscription {
+    pub fn redacted(mut self) -> Self {
+        self.webhook = self.webhook.map(WebhookEvent::redacted);
+        self
+    }
+}
+
+/// Signed by the follower to follow a space, or to change the notifications
+/// of a space they already follow.
+#[derive(CandidType, Deserialize, Debug, Clone, Serialize)]
+pub struct SubscriptionMessage {
+    pub space_id: u32,
+    pub address: String,
+    pub triggers: Vec<EventTrigger>,
+    pub webhook: Option<WebhookEvent>,
+    pub timestamp: u64,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct SubscriptionData {
+    pub signature: String,
+    pub message: SubscriptionMessage,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, Default, Serialize)]
+pub struct UnfollowMessage {
+    pub space_id: u32,
+    pub address: String,
+    pub timestamp: u64,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, Default)]
+pub struct UnfollowData {
+    pub signature: String,
+    pub message: UnfollowMessage,
+}
@@ -7,6 +7,13 @@ pub struct VoteMessage {
     pub space_id: u32,
     pub option_id: u32,
     pub address: String,
+    // Optional fields are left out of the signed JSON when unset, so messages
+    // signed by older clients keep verifying.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Required to change a vote; must be newer than the replaced vote.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
 }
 
 #[derive(CandidType, Deserialize, Debug, Clone, Default)]
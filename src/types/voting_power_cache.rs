@@ -0,0 +1,47 @@
+use candid::{CandidType, Decode, Deserialize, Encode, Nat};
+use ic_stable_structures::{storable::Bound, Storable};
+use std::borrow::Cow;
+
+const MAX_KEY_SIZE: u32 = 200;
+
+/// Key of a cached strategy result. Fields are ordered so that all entries of
+/// one strategy are adjacent, which lets them be invalidated with a range scan.
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct VotingPowerCacheKey {
+    pub space_id: u32,
+    pub strategy_id: u32,
+    pub address: String,
+    pub block: u64,
+}
+
+impl Storable for VotingPowerCacheKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: MAX_KEY_SIZE,
+        is_fixed_size: false,
+    };
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct CachedVotingPower {
+    pub voting_power: Nat,
+}
+
+impl Storable for CachedVotingPower {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
@@ -0,0 +1,58 @@
+use candid::{CandidType, Decode, Deserialize, Encode};
+use ic_stable_structures::{storable::Bound, Storable};
+use std::borrow::Cow;
+
+use super::event::EventTrigger;
+
+const MAX_KEY_SIZE: u32 = 100;
+
+/// Deliveries of a space are adjacent and ordered by id.
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct WebhookDeliveryKey {
+    pub space_id: u32,
+    pub delivery_id: u64,
+}
+
+impl Storable for WebhookDeliveryKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: MAX_KEY_SIZE,
+        is_fixed_size: false,
+    };
+}
+
+/// Outcome of one webhook request, kept so space admins and followers can
+/// see whether their endpoint accepts the notifications.
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct WebhookDelivery {
+    pub id: u64,
+    pub space_id: u32,
+    pub event_trigger: EventTrigger,
+    // Only the host, the full url may contain a token
+    pub webhook_host: String,
+    // Follower the webhook belongs to, `None` for the space's own events
+    pub subscriber: Option<String>,
+    pub timestamp: u64,
+    // HTTP status of the response, `None` when the request failed
+    pub status: Option<u32>,
+    pub error: Option<String>,
+}
+
+impl Storable for WebhookDelivery {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
@@ -1,8 +1,97 @@
 use candid::CandidType;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(CandidType, Deserialize, Debug, Clone)]
+const MAX_PAYLOAD_LENGTH: usize = 10_000;
+const MAX_HEADERS: usize = 10;
+const MIN_SECRET_LENGTH: usize = 16;
+
+// Set by the canister on every delivery
+pub const SIGNATURE_HEADER: &str = "X-DecGov-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-DecGov-Timestamp";
+const RESERVED_HEADERS: [&str; 4] = [
+    "content-type",
+    "content-length",
+    "x-decgov-signature",
+    "x-decgov-timestamp",
+];
+
+/// Posts `payload` to `webhook_url`, with the `${name}` variables listed in
+/// `services/events.rs` substituted.
+#[derive(CandidType, Deserialize, Debug, Clone, Serialize)]
 pub struct WebhookEvent {
     pub webhook_url: String,
     pub payload: String,
-}
\ No newline at end of file
+    // Key of the HMAC-SHA256 signature sent with each delivery. Queries leave
+    // it out, but it is public when the event is added by a proposal.
+    pub secret: Option<String>,
+    // Sent in addition to Content-Type and the signature headers. Queries
+    // only return their names.
+    pub headers: Vec<WebhookHeader>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, Serialize)]
+pub struct WebhookHeader {
+    pub name: String,
+    pub value: String,
+}
+
+impl WebhookEvent {
+    pub fn check(&self) -> Result<(), String> {
+        if !self.webhook_url.starts_with("https://") {
+            return Err("Webhook url must use https".to_owned());
+        }
+
+        if self.payload.len() > MAX_PAYLOAD_LENGTH {
+            return Err("Webhook payload is too long".to_owned());
+        }
+
+        if let Some(ref secret) = self.secret {
+            if secret.len() < MIN_SECRET_LENGTH {
+                return Err(format!(
+                    "Webhook secret must have at least {} characters",
+                    MIN_SECRET_LENGTH
+                ));
+            }
+        }
+
+        if self.headers.len() > MAX_HEADERS {
+            return Err("Webhook has too many headers".to_owned());
+        }
+
+        for header in self.headers.iter() {
+            let name = header.name.to_lowercase();
+            if RESERVED_HEADERS.contains(&name.as_str()) {
+                return Err(format!(
+                    "Webhook header {} is set by the canister",
+                    header.name
+                ));
+            }
+
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+                return Err(format!("Invalid webhook header name {}", header.name));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The webhook without its secret, header values and the path of its url,
+    /// which may carry credentials, for query results.
+    pub fn redacted(mut self) -> Self {
+        self.webhook_url = webhook_host(&self.webhook_url);
+        self.secret = None;
+        for header in self.headers.iter_mut() {
+            header.value.clear();
+        }
+        self
+    }
+}
+
+/// Host of a webhook url. Paths and queries of services like Discord and
+/// Slack contain the webhook's token.
+pub fn webhook_host(webhook_url: &str) -> String {
+    url::Url::parse(webhook_url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_owned))
+        .unwrap_or_default()
+}
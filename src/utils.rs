@@ -1,4 +1,7 @@
+use candid::Nat;
+use ethers_core::types::{Address, Signature};
 use hex::FromHexError;
+use serde::Serialize;
 
 pub fn from_hex(data: &str) -> Result<Vec<u8>, FromHexError> {
     hex::decode(&data[2..])
@@ -7,3 +10,37 @@ pub fn from_hex(data: &str) -> Result<Vec<u8>, FromHexError> {
 pub fn to_hex(data: &[u8]) -> String {
     format!("0x{}", hex::encode(data))
 }
+
+/// Checks that `signature` is a personal signature of the JSON encoded
+/// `message` made by `address`, and returns the parsed address.
+pub fn verify_signature<T: Serialize>(
+    message: &T,
+    signature: &str,
+    address: &str,
+) -> Result<Address, String> {
+    let message_json = serde_json::to_string(message).map_err(|_| "Invalid message")?;
+    let signature = signature
+        .parse::<Signature>()
+        .map_err(|_| "Invalid signature")?;
+    let recovered_address = signature
+        .recover(message_json)
+        .map_err(|_| "Invalid signature")?;
+    let parsed_address = address.parse::<Address>().map_err(|_| "Invalid address")?;
+
+    if recovered_address != parsed_address {
+        return Err("Invalid signature".to_owned());
+    }
+
+    Ok(recovered_address)
+}
+
+/// Lowercase `0x` prefixed form used to compare and store addresses.
+pub fn normalize_address(address: &Address) -> String {
+    format!("{:#x}", address)
+}
+
+/// Serializes a `Nat` as a decimal string in signed JSON messages, instead of
+/// the digit array serde would produce.
+pub fn serialize_nat<S: serde::Serializer>(value: &Nat, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&value.0.to_string())
+}
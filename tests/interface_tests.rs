@@ -0,0 +1,87 @@
+const CANDID_INTERFACE: &str = include_str!("../backend.did");
+
+fn service_methods() -> Vec<String> {
+    let service = CANDID_INTERFACE
+        .split("service : {")
+        .nth(1)
+        .expect("backend.did has no service definition");
+
+    service
+        .lines()
+        .filter_map(|line| line.trim().split_once(" : "))
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
+
+#[test]
+pub fn test_vote_is_the_only_vote_creating_method() {
+    let methods = service_methods();
+
+    assert!(methods.contains(&"vote".to_string()));
+
+    for method in [
+        "insert_vote",
+        "update_vote",
+        "update_space_proposals",
+        "update_strategies",
+    ] {
+        assert!(
+            !methods.contains(&method.to_string()),
+            "{} must not be part of the public interface",
+            method
+        );
+    }
+}
+
+#[test]
+pub fn test_space_management_methods_require_authorization() {
+    let service = CANDID_INTERFACE
+        .split("service : {")
+        .nth(1)
+        .expect("backend.did has no service definition");
+
+    for method in [
+        "update_space",
+        "delete_space",
+        "publish_proposal",
+        "update_proposal",
+        "delete_proposal",
+        "insert_proposal_option",
+        "delete_proposal_option",
+        "insert_evm_strategy",
+        "update_evm_strategy",
+        "delete_strategy",
+        "insert_event",
+        "propose_owner",
+        "set_owners",
+        "save_proposal_template",
+        "delete_proposal_template",
+    ] {
+        let signature = service
+            .split(&format!("  {} : (", method))
+            .nth(1)
+            .unwrap_or_else(|| panic!("{} is missing from the interface", method));
+        let arguments = signature.split(") ->").next().unwrap();
+
+        assert!(
+            arguments.contains("Authorization"),
+            "{} must take an Authorization",
+            method
+        );
+    }
+}
+
+#[test]
+pub fn test_vote_removal_is_controller_only() {
+    // Whitespace is dropped so that formatting and line endings don't matter.
+    let source: String = include_str!("../src/lib.rs")
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+
+    assert!(service_methods().contains(&"delete_vote".to_string()));
+    assert!(
+        source.contains(r#"#[update(guard="caller_is_controller")]fndelete_vote("#),
+        "delete_vote must only be callable by controllers"
+    );
+}